@@ -1,33 +1,51 @@
-use std::{path::PathBuf, sync::Arc};
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+};
 
 use clap::crate_version;
 use handlebars::Handlebars;
 use rmcp::{
-    Error as McpError, ServerHandler,
+    ErrorData as McpError, Peer, RoleServer, ServerHandler,
     handler::server::tool::{ToolCallContext, ToolRoute, ToolRouter},
     model::*,
+    service::{NotificationContext, RequestContext},
     tool_handler,
 };
 use serde_json::{Map, Value as JsonValue};
 
-use crate::manifest::{CommandSpec, Manifest};
+use crate::{
+    logging::Logger,
+    manifest::{CommandSpec, Manifest},
+    port_lease::PortLeases,
+    serial_monitor::SerialMonitors,
+    tool_filter::ToolFilter,
+};
 
 #[derive(Clone)]
 pub struct Commands {
     tool_router: ToolRouter<Self>,
     pub cwd: PathBuf,
     pub manifest: Manifest,
+    pub logger: Logger,
+    pub monitors: SerialMonitors,
+    pub leases: PortLeases,
+    /// Baud rates set by `serial_set_baudrate`, keyed by canonical port
+    pub baudrates: Arc<Mutex<BTreeMap<String, u32>>>,
     handlebars: Handlebars<'static>,
 }
 
 impl Commands {
-    pub fn new(cwd: PathBuf, manifest: Manifest) -> Self {
+    pub fn new(
+        cwd: PathBuf,
+        manifest: Manifest,
+        logger: Logger,
+        filter: &ToolFilter,
+        namespace: Option<&str>,
+    ) -> Result<Self, String> {
         let mut tool_router = ToolRouter::<Self>::new();
 
-        for (name, spec) in manifest.commands.iter() {
-            tool_router.add_route(spec.to_tool_route(name));
-        }
-
         if let Some(opts) = &manifest.flash
             && opts.enabled
         {
@@ -38,6 +56,7 @@ impl Commands {
             && opts.enabled
         {
             tool_router.merge(Self::serial_router());
+            tool_router.merge(Self::serial_monitor_router());
         }
 
         if let Some(opts) = &manifest.schematic
@@ -46,27 +65,116 @@ impl Commands {
             tool_router.merge(Self::schematic_router());
         }
 
-        Self {
+        for (name, spec) in manifest.commands.iter() {
+            if tool_router.has_route(name) {
+                return Err(format!("Command '{}' is named after a built-in tool", name));
+            }
+            tool_router.add_route(spec.to_tool_route(name));
+        }
+
+        if let Some(opts) = &manifest.serial
+            && opts.enabled
+        {
+            for (name, scenario) in manifest.serial_scenarios.iter() {
+                if tool_router.has_route(name) {
+                    return Err(format!(
+                        "Serial scenario '{}' is named after a built-in tool or a command",
+                        name
+                    ));
+                }
+                tool_router.add_route(scenario.to_tool_route(name));
+            }
+        }
+
+        let names = tool_router
+            .map
+            .keys()
+            .map(|name| name.as_ref())
+            .collect::<Vec<_>>();
+        for pattern in filter.unmatched(&names) {
+            logger.warning(
+                "manifest",
+                format!("Tool pattern '{}' matches no tool", pattern),
+            );
+        }
+        tool_router.map.retain(|name, _| filter.allows(name));
+
+        if let Some(namespace) = namespace {
+            tool_router = Self::namespaced(tool_router, namespace, &cwd);
+        }
+
+        Ok(Self {
             tool_router,
             cwd,
             manifest,
+            logger,
+            monitors: SerialMonitors::default(),
+            leases: PortLeases::default(),
+            baudrates: Default::default(),
             handlebars: Handlebars::new(),
+        })
+    }
+
+    /// Prefix names of all tools with the namespace, and the descriptions
+    /// with the project name, so that tools from multiple projects can be
+    /// told apart by the client.
+    fn namespaced(tool_router: ToolRouter<Self>, namespace: &str, cwd: &Path) -> ToolRouter<Self> {
+        let project = cwd
+            .canonicalize()
+            .ok()
+            .and_then(|path| {
+                path.file_name()
+                    .map(|name| name.to_string_lossy().to_string())
+            })
+            .unwrap_or_else(|| namespace.to_string());
+
+        let mut namespaced = ToolRouter::<Self>::new();
+        for mut route in tool_router {
+            route.attr.name = format!("{}_{}", namespace, route.attr.name).into();
+            route.attr.description = route
+                .attr
+                .description
+                .map(|description| format!("[{}] {}", project, description).into());
+            namespaced.add_route(route);
         }
+        namespaced
     }
 
     async fn execute(
         &self,
+        peer: &Peer<RoleServer>,
+        name: &str,
         spec: &CommandSpec,
         args: &JsonValue,
     ) -> Result<CallToolResult, McpError> {
         spec.validate(args)
             .map_err(|e| McpError::invalid_params(format!("Invalid argument: {}", e), None))?;
 
-        let (_command, output, exit_code) = spec
-            .execute(&self.handlebars, args, &self.cwd)
-            .map_err(|e| {
-                McpError::invalid_params(format!("Command execution error: {}", e), None)
-            })?;
+        let command = spec.render(&self.handlebars, args)?;
+
+        if spec.confirm {
+            self.confirm(peer, name, &command).await?;
+        }
+
+        self.logger
+            .info("commands", format!("Running command '{}'", name));
+        self.logger.debug("commands", command.as_str());
+
+        let (output, exit_code) = spec.execute(&command, &self.cwd).map_err(|e| {
+            self.logger
+                .error("commands", format!("Command '{}' failed: {}", name, e));
+            McpError::invalid_params(format!("Command execution error: {}", e), None)
+        })?;
+
+        if exit_code == 0 {
+            self.logger
+                .info("commands", format!("Command '{}' exited with code 0", name));
+        } else {
+            self.logger.warning(
+                "commands",
+                format!("Command '{}' exited with code {}", name, exit_code),
+            );
+        }
 
         let mut response = String::new();
 
@@ -86,29 +194,59 @@ impl Commands {
 impl ServerHandler for Commands {
     fn get_info(&self) -> ServerInfo {
         ServerInfo {
-            protocol_version: ProtocolVersion::V_2024_11_05,
-            capabilities: ServerCapabilities::builder().enable_tools().build(),
+            protocol_version: ProtocolVersion::V_2025_06_18,
+            capabilities: ServerCapabilities::builder()
+                .enable_logging()
+                .enable_tools()
+                .build(),
             server_info: Implementation {
                 name: "commands".to_string(),
+                title: None,
                 version: crate_version!().to_string(),
+                icons: None,
+                website_url: None,
             },
             instructions: None,
         }
     }
+
+    async fn set_level(
+        &self,
+        SetLevelRequestParam { level }: SetLevelRequestParam,
+        _context: RequestContext<RoleServer>,
+    ) -> Result<(), McpError> {
+        self.logger.set_level(level);
+        Ok(())
+    }
+
+    async fn on_initialized(&self, context: NotificationContext<RoleServer>) {
+        self.logger.attach(context.peer);
+    }
 }
 
 impl CommandSpec {
     pub fn to_tool_route(&self, name: &String) -> ToolRoute<Commands> {
+        let command = name.clone();
+
         ToolRoute::<Commands> {
             attr: Tool {
                 name: name.to_string().into(),
+                title: None,
                 description: Some(self.description.as_str().to_string().into()),
                 input_schema: Arc::new(self.to_schema()),
-                annotations: None,
+                output_schema: None,
+                annotations: self
+                    .confirm
+                    .then(|| ToolAnnotations::new().destructive(true)),
+                icons: None,
             },
-            call: Arc::new(|tcc: ToolCallContext<'_, Commands>| {
+            call: Arc::new(move |tcc: ToolCallContext<'_, Commands>| {
+                // Look up by the command name instead of the requested tool
+                // name, which may have been namespaced
+                let name = command.clone();
+
                 Box::pin(async move {
-                    let name = tcc.name.as_ref();
+                    let name = name.as_str();
 
                     let spec = tcc.service.manifest.commands.get(name).ok_or_else(|| {
                         McpError::invalid_params(format!("Command '{}' not found", name), None)
@@ -119,7 +257,9 @@ impl CommandSpec {
                         None => JsonValue::Object(Map::new()),
                     };
 
-                    tcc.service.execute(spec, &args).await
+                    tcc.service
+                        .execute(&tcc.request_context.peer, name, spec, &args)
+                        .await
                 })
             }),
         }
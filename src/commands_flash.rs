@@ -1,15 +1,30 @@
-use std::{str::FromStr, time::Duration};
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+    process,
+    str::FromStr,
+    sync::atomic::{AtomicUsize, Ordering},
+    time::{Duration, Instant},
+};
 
 use cskburn::{CSKBurn, Family, Image, ProbeTarget, WriteTarget};
 use rmcp::{
-    Error as McpError,
-    handler::server::tool::Parameters,
-    model::{CallToolResult, Content},
+    ErrorData as McpError, Peer, RoleServer,
+    handler::server::wrapper::Parameters,
+    model::{CallToolResult, Content, Meta},
     schemars, tool, tool_router,
 };
 use serde::Deserialize;
 
-use crate::commands::Commands;
+use crate::{
+    commands::Commands,
+    flash_format::{ImageFormat, map_to_flash, parse_image},
+    manifest::{FlashImage, FlashOptions},
+    port_lease::PortLease,
+    progress::Progress,
+    serial_monitor::PausedMonitor,
+    serial_ports::list_ports,
+};
 
 const PROBE_RESET_ATTEMPTS: usize = 5;
 const PROBE_SYNC_ATTEMPTS: usize = 3;
@@ -17,11 +32,29 @@ const RESET_INTERVAL: Duration = Duration::from_millis(100);
 
 #[derive(Debug, Deserialize, schemars::JsonSchema)]
 pub struct FlashWriteRequest {
-    #[schemars(description = "The port to use for flashing the device")]
+    #[schemars(description = "The port to use for flashing the device, or its alias")]
     pub port: String,
 
-    #[schemars(description = "The path to the binary file to flash")]
-    pub path: String,
+    #[schemars(
+        description = "The path to an image to flash, either a raw binary flashed at offset 0, \
+            or an Intel HEX, ELF or UF2 image flashed at its own addresses"
+    )]
+    pub path: Option<String>,
+
+    #[schemars(
+        description = "The format of the image of 'path', detected from its extension or \
+            content by default"
+    )]
+    pub format: Option<ImageFormat>,
+
+    #[schemars(
+        description = "Images to flash, with the address of each raw binary, Intel HEX, ELF \
+            and UF2 images carrying their own"
+    )]
+    pub images: Option<Vec<FlashImage>>,
+
+    #[schemars(description = "Name of a flash layout defined in the manifest to flash")]
+    pub layout: Option<String>,
 }
 
 #[tool_router(router = flash_router, vis = "pub")]
@@ -35,6 +68,18 @@ impl Commands {
         let port = cskburn::list_ports()
             .map_err(|e| McpError::internal_error(format!("Failed to list ports: {}", e), None))?;
 
+        // Describe the USB attributes of the ports, if known
+        let usb_ports = list_ports().unwrap_or_default();
+        let port = port
+            .into_iter()
+            .map(
+                |name| match usb_ports.iter().find(|info| info.name == name) {
+                    Some(info) => info.describe(&self.port_aliases(info)),
+                    None => name,
+                },
+            )
+            .collect::<Vec<_>>();
+
         Ok(CallToolResult::success(vec![Content::text(
             port.join("\n"),
         )]))
@@ -42,51 +87,220 @@ impl Commands {
 
     #[tool(
         name = "flash_write",
-        description = "Write target binary to device via specified port"
+        description = "Write target images (raw binary, Intel HEX or ELF) to device via \
+            specified port, either a single image, a list of images at their addresses, or a \
+            flash layout defined in the manifest",
+        annotations(destructive_hint = true)
     )]
     async fn flash_write(
         &self,
-        Parameters(FlashWriteRequest { port, path }): Parameters<FlashWriteRequest>,
+        peer: Peer<RoleServer>,
+        meta: Meta,
+        Parameters(FlashWriteRequest {
+            port,
+            path,
+            format,
+            images,
+            layout,
+        }): Parameters<FlashWriteRequest>,
     ) -> Result<CallToolResult, McpError> {
-        let flash_opts = self
-            .manifest
-            .flash
-            .as_ref()
-            .ok_or(McpError::invalid_params(
-                "Flash options are not defined in the manifest".to_string(),
-                None,
-            ))?;
+        let port = self.resolve_port(&port)?;
+
+        let flash_opts = self.flash_options()?;
+
+        // Read all images before touching the device, so that a missing or
+        // invalid file doesn't leave it half flashed
+        let images = self.flash_images(path, format, images, layout)?;
+        let segments = self.flash_segments(&images)?;
+
+        let target = segments
+            .iter()
+            .map(|segment| {
+                format!(
+                    "* {} at 0x{:08x} ({} bytes)",
+                    segment.path,
+                    segment.offset,
+                    segment.data.len()
+                )
+            })
+            .collect::<Vec<_>>()
+            .join("\n");
+        self.confirm(
+            &peer,
+            "flash_write",
+            &format!("Flash {}:\n\n{}", port, target),
+        )
+        .await?;
+
+        // cskburn reads images from files, so the segments parsed out of the
+        // images are staged in temporary files
+        let files = segments
+            .iter()
+            .map(SegmentFile::create)
+            .collect::<Result<Vec<_>, _>>()?;
+        let mut sources = segments
+            .iter()
+            .zip(&files)
+            .map(|(segment, file)| file.image(segment))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let mut reports = segments
+            .iter()
+            .map(|segment| ImageReport {
+                segment,
+                elapsed: None,
+            })
+            .collect::<Vec<_>>();
+
+        let mut stages = FlashStages::new(Progress::new(peer, &meta));
+        let mut session = self.flash_connect(&port, "flash_write", &mut stages)?;
+        let cskburn = &mut session.cskburn;
+
+        // All images are written in the same burner session, with the
+        // progress of the write stage advancing as each of them is written
+        let total = reports.iter().map(|report| report.size()).sum::<u64>();
+        let mut done = 0;
+
+        stages.begin(FlashStage::Write);
+        for (report, source) in reports.iter_mut().zip(sources.iter_mut()) {
+            self.logger.info(
+                "flash",
+                format!(
+                    "Writing {} at 0x{:08x}",
+                    report.segment.path, report.segment.offset
+                ),
+            );
+
+            let started = Instant::now();
+            cskburn.write(source, WriteTarget::Flash).map_err(|e| {
+                McpError::internal_error(
+                    format!("Failed to write image {}: {}", report.segment.path, e),
+                    None,
+                )
+            })?;
+            done += report.size();
+            report.elapsed = Some(started.elapsed());
+            stages.advance(done, total);
+        }
+        stages.end(Some(total));
+
+        flash_reboot(cskburn, &mut stages)?;
+
+        self.logger.info(
+            "flash",
+            format!(
+                "Flashed {} segment(s) of {} image(s) to {}",
+                segments.len(),
+                images.len(),
+                port
+            ),
+        );
+
+        let mut response = device_info(&port, flash_opts);
+        response.push_str("## Flashing Summary\n\n");
+        response.push_str("| Image | Format | Offset | Size | Elapsed |\n");
+        response.push_str("| ----- | ------ | ------ | ---- | ------- |\n");
+        for report in &reports {
+            response.push_str(&format!(
+                "| {} | {} | 0x{:08x} | {} | {} |\n",
+                report.segment.path,
+                report.segment.format,
+                report.segment.offset,
+                report.size(),
+                match report.elapsed {
+                    Some(elapsed) => format!("{:.3}s", elapsed.as_secs_f64()),
+                    None => "-".to_string(),
+                },
+            ));
+        }
+        response.push_str("\n");
+        response.push_str("## Stages\n\n");
+        response.push_str(&stages.summary());
+        response.push_str("\n");
+        response.push_str("## Operation Status\n\n");
+        response.push_str("Operation completed successfully.\n");
+
+        Ok(CallToolResult::success(vec![Content::text(response)]))
+    }
+}
+
+/// A device booted into the burner, ready for flash operations. The port is
+/// leased, and its monitor paused, until the session is dropped.
+struct FlashSession {
+    cskburn: CSKBurn,
+    _lease: PortLease,
+    _paused: Option<PausedMonitor>,
+}
+
+impl Commands {
+    fn flash_options(&self) -> Result<&FlashOptions, McpError> {
+        self.manifest.flash.as_ref().ok_or(McpError::invalid_params(
+            "Flash options are not defined in the manifest".to_string(),
+            None,
+        ))
+    }
+
+    /// Connect to the device on the port, reset it into the ROM and boot the
+    /// burner, for the operation named by `holder`.
+    fn flash_connect(
+        &self,
+        port: &str,
+        holder: &str,
+        stages: &mut FlashStages,
+    ) -> Result<FlashSession, McpError> {
+        let flash_opts = self.flash_options()?;
 
         let chip =
             Family::from_str(&flash_opts.chip).map_err(|e| McpError::invalid_params(e, None))?;
-
         let mut burner = chip.burner();
 
-        let mut cskburn = CSKBurn::connect(&port, flash_opts.baudrate, chip)
+        // Release the port from its monitor while flashing, which is resumed
+        // once the lease of the port is dropped
+        let paused = self.monitors.pause(port);
+        if paused.is_some() {
+            self.logger
+                .info("flash", format!("Paused the monitor of {}", port));
+        }
+        let lease = self.leases.acquire(port, holder)?;
+
+        self.logger.info(
+            "flash",
+            format!("Connecting to {} at {} baud", port, flash_opts.baudrate),
+        );
+
+        let mut cskburn = CSKBurn::connect(port, flash_opts.baudrate, chip)
             .map_err(|e| McpError::internal_error(format!("Failed to open device: {}", e), None))?;
 
         let mut probed = false;
         for _ in 0..PROBE_RESET_ATTEMPTS {
+            stages.begin(FlashStage::Reset);
             cskburn.reset(true, Some(RESET_INTERVAL)).map_err(|e| {
                 McpError::internal_error(format!("Failed to reset device: {}", e), None)
             })?;
+            stages.end(None);
 
-            if cskburn
+            stages.begin(FlashStage::Probe);
+            probed = cskburn
                 .probe(ProbeTarget::ROM, Some(PROBE_SYNC_ATTEMPTS))
-                .is_ok()
-            {
-                probed = true;
+                .is_ok();
+            stages.end(None);
+
+            if probed {
                 break;
             }
         }
 
         if !probed {
+            self.logger.error("flash", "Failed to probe device");
             return Err(McpError::internal_error(
                 "Failed to probe device after multiple attempts".to_string(),
                 None,
             ));
         }
 
+        self.logger.info("flash", "Device probed, uploading burner");
+
+        stages.begin(FlashStage::Burner);
         cskburn
             .write(&mut burner, WriteTarget::Memory { action: None })
             .map_err(|e| {
@@ -96,41 +310,342 @@ impl Commands {
         cskburn
             .probe(ProbeTarget::Burner, Some(PROBE_SYNC_ATTEMPTS))
             .map_err(|e| McpError::internal_error(format!("Failed to boot burner: {}", e), None))?;
+        stages.end(None);
 
-        let path = self.cwd.join(&path);
-        let path = path.to_str().ok_or(McpError::invalid_params(
-            "Invalid path for binary file".to_string(),
-            None,
-        ))?;
+        Ok(FlashSession {
+            cskburn,
+            _lease: lease,
+            _paused: paused,
+        })
+    }
 
-        let mut source = Image::try_from_file(0, path)
-            .map_err(|e| McpError::invalid_params(format!("Failed to read image: {}", e), None))?;
+    /// Images to flash, from exactly one of a single path, a list of images,
+    /// or a layout of the manifest. Paths are resolved against the working
+    /// directory, and the images are checked not to overlap.
+    fn flash_images(
+        &self,
+        path: Option<String>,
+        format: Option<ImageFormat>,
+        images: Option<Vec<FlashImage>>,
+        layout: Option<String>,
+    ) -> Result<Vec<FlashImage>, McpError> {
+        if format.is_some() && path.is_none() {
+            return Err(McpError::invalid_params(
+                "'format' only applies to 'path', set the format of each image instead".to_string(),
+                None,
+            ));
+        }
 
-        cskburn
-            .write(&mut source, WriteTarget::Flash)
-            .map_err(|e| McpError::internal_error(format!("Failed to write image: {}", e), None))?;
+        let images = match (path, images, layout) {
+            (Some(path), None, None) => vec![FlashImage {
+                path,
+                address: None,
+                format,
+            }],
+            (None, Some(images), None) => images,
+            (None, None, Some(layout)) => {
+                let layouts = self
+                    .manifest
+                    .flash
+                    .as_ref()
+                    .map(|opts| &opts.layouts)
+                    .filter(|layouts| !layouts.is_empty())
+                    .ok_or(McpError::invalid_params(
+                        "No flash layouts are defined in the manifest".to_string(),
+                        None,
+                    ))?;
+                layouts.get(&layout).cloned().ok_or_else(|| {
+                    McpError::invalid_params(
+                        format!(
+                            "Flash layout '{}' is not defined, available layouts: {}",
+                            layout,
+                            layouts.keys().cloned().collect::<Vec<_>>().join(", ")
+                        ),
+                        None,
+                    )
+                })?
+            }
+            _ => {
+                return Err(McpError::invalid_params(
+                    "Exactly one of 'path', 'images' and 'layout' must be specified".to_string(),
+                    None,
+                ));
+            }
+        };
+
+        if images.is_empty() {
+            return Err(McpError::invalid_params(
+                "No images to flash".to_string(),
+                None,
+            ));
+        }
+
+        images
+            .into_iter()
+            .map(|image| {
+                let path = self.cwd.join(&image.path);
+                let path = path.to_str().ok_or(McpError::invalid_params(
+                    format!("Invalid path for image: {}", image.path),
+                    None,
+                ))?;
+                Ok(FlashImage {
+                    path: path.to_string(),
+                    address: image.address,
+                    format: image.format,
+                })
+            })
+            .collect()
+    }
+
+    /// Read and parse the images into segments at their offsets in flash,
+    /// checking that they fit in flash and don't overlap.
+    fn flash_segments(&self, images: &[FlashImage]) -> Result<Vec<FlashSegment>, McpError> {
+        let flash_opts = self.flash_options()?;
+        let (base, size) = (
+            flash_opts.base.map(|base| base.0),
+            flash_opts.size.map(|size| size.0),
+        );
 
-        cskburn.reset(false, Some(RESET_INTERVAL)).map_err(|e| {
+        let mut segments = Vec::new();
+        for image in images {
+            let data = fs::read(&image.path).map_err(|e| {
+                McpError::invalid_params(
+                    format!("Failed to read image {}: {}", image.path, e),
+                    None,
+                )
+            })?;
+            let invalid = |e| McpError::invalid_params(format!("{}: {}", image.path, e), None);
+            let (format, parsed) = parse_image(
+                Path::new(&image.path),
+                data,
+                image.address.map(|address| address.0),
+                image.format,
+            )
+            .map_err(invalid)?;
+
+            for segment in map_to_flash(format, parsed, base, size).map_err(invalid)? {
+                segments.push(FlashSegment {
+                    path: image.path.clone(),
+                    format,
+                    offset: segment.address,
+                    data: segment.data,
+                });
+            }
+        }
+
+        let mut ranges = segments.iter().collect::<Vec<_>>();
+        ranges.sort_by_key(|segment| segment.offset);
+        for pair in ranges.windows(2) {
+            let (segment, next) = (pair[0], pair[1]);
+            if segment.offset as u64 + segment.data.len() as u64 > next.offset as u64 {
+                return Err(McpError::invalid_params(
+                    format!(
+                        "{} at 0x{:08x} overlaps with {} at 0x{:08x}",
+                        segment.path, segment.offset, next.path, next.offset
+                    ),
+                    None,
+                ));
+            }
+        }
+
+        Ok(segments)
+    }
+}
+
+/// Contiguous bytes of an image, to write at an offset in flash
+struct FlashSegment {
+    path: String,
+    format: ImageFormat,
+    offset: u32,
+    data: Vec<u8>,
+}
+
+/// A segment staged in a temporary file, removed once dropped
+struct SegmentFile(PathBuf);
+
+impl SegmentFile {
+    fn create(segment: &FlashSegment) -> Result<Self, McpError> {
+        static NEXT_FILE: AtomicUsize = AtomicUsize::new(0);
+
+        let path = env::temp_dir().join(format!(
+            "commands-mcp-segment-{}-{}.bin",
+            process::id(),
+            NEXT_FILE.fetch_add(1, Ordering::Relaxed)
+        ));
+        fs::write(&path, &segment.data).map_err(|e| {
             McpError::internal_error(
-                format!("Failed to reset device after flashing: {}", e),
+                format!("Failed to stage image {}: {}", segment.path, e),
                 None,
             )
         })?;
+        Ok(Self(path))
+    }
 
-        let mut response = String::new();
-        response.push_str("## Device Info\n\n");
-        response.push_str(format!("* Port: {}\n", port).as_str());
-        response.push_str(format!("* Baud rate: {}\n", flash_opts.baudrate).as_str());
-        response.push_str(format!("* Chip model: {}\n", flash_opts.chip).as_str());
-        response.push_str("\n");
-        response.push_str("## Flashing Summary\n\n");
-        response.push_str(format!("* Image to flash: {}\n", path).as_str());
-        response.push_str(format!("* Offset: 0x{:08x}\n", source.addr).as_str());
-        response.push_str(format!("* Size: {}\n", source.size().unwrap_or(0)).as_str());
-        response.push_str("\n");
-        response.push_str("## Operation Status\n\n");
-        response.push_str("Operation completed successfully.\n");
+    /// The image to write the segment at its offset
+    fn image(&self, segment: &FlashSegment) -> Result<Image, McpError> {
+        self.0
+            .to_str()
+            .ok_or_else(|| "invalid temporary path".to_string())
+            .and_then(|path| Image::try_from_file(segment.offset, path).map_err(|e| e.to_string()))
+            .map_err(|e| {
+                McpError::internal_error(
+                    format!("Failed to stage image {}: {}", segment.path, e),
+                    None,
+                )
+            })
+    }
+}
 
-        Ok(CallToolResult::success(vec![Content::text(response)]))
+impl Drop for SegmentFile {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.0);
+    }
+}
+
+struct ImageReport<'a> {
+    segment: &'a FlashSegment,
+
+    /// Time spent writing the image
+    elapsed: Option<Duration>,
+}
+
+impl ImageReport<'_> {
+    fn size(&self) -> u64 {
+        self.segment.data.len() as u64
+    }
+}
+
+/// Reset the device out of the burner, to run the firmware.
+fn flash_reboot(cskburn: &mut CSKBurn, stages: &mut FlashStages) -> Result<(), McpError> {
+    stages.begin(FlashStage::Reboot);
+    cskburn.reset(false, Some(RESET_INTERVAL)).map_err(|e| {
+        McpError::internal_error(
+            format!("Failed to reset device after flashing: {}", e),
+            None,
+        )
+    })?;
+    stages.end(None);
+    Ok(())
+}
+
+/// Header of the results of flash tools, describing the device
+fn device_info(port: &str, flash_opts: &FlashOptions) -> String {
+    let mut info = String::new();
+    info.push_str("## Device Info\n\n");
+    info.push_str(format!("* Port: {}\n", port).as_str());
+    info.push_str(format!("* Baud rate: {}\n", flash_opts.baudrate).as_str());
+    info.push_str(format!("* Chip model: {}\n", flash_opts.chip).as_str());
+    info.push_str("\n");
+    info
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum FlashStage {
+    Reset,
+    Probe,
+    Burner,
+    Write,
+    Reboot,
+}
+
+impl FlashStage {
+    fn name(&self) -> &'static str {
+        match self {
+            FlashStage::Reset => "Reset",
+            FlashStage::Probe => "Probe",
+            FlashStage::Burner => "Burner upload",
+            FlashStage::Write => "Erase & write",
+            FlashStage::Reboot => "Reboot",
+        }
+    }
+
+    /// Range of the overall progress (in percent) covered by the stage
+    fn range(&self) -> (f64, f64) {
+        match self {
+            FlashStage::Reset => (0.0, 2.0),
+            FlashStage::Probe => (2.0, 5.0),
+            FlashStage::Burner => (5.0, 15.0),
+            FlashStage::Write => (15.0, 99.0),
+            FlashStage::Reboot => (99.0, 100.0),
+        }
+    }
+}
+
+/// Timing of each flashing stage, reported as progress while flashing and
+/// summarized afterwards.
+struct FlashStages {
+    progress: Progress,
+    current: Option<(FlashStage, Instant)>,
+    finished: Vec<(FlashStage, Duration, Option<u64>)>,
+}
+
+impl FlashStages {
+    fn new(progress: Progress) -> Self {
+        Self {
+            progress,
+            current: None,
+            finished: Vec::new(),
+        }
+    }
+
+    fn begin(&mut self, stage: FlashStage) {
+        self.current = Some((stage, Instant::now()));
+        self.progress
+            .report(stage.range().0, Some(100.0), stage.name());
+    }
+
+    fn advance(&self, written: u64, total: u64) {
+        let Some((stage, _)) = self.current else {
+            return;
+        };
+
+        let (start, end) = stage.range();
+        let ratio = if total > 0 {
+            written as f64 / total as f64
+        } else {
+            0.0
+        };
+
+        self.progress.report(
+            start + (end - start) * ratio,
+            Some(100.0),
+            format!("{}: {}/{} bytes", stage.name(), written, total),
+        );
+    }
+
+    /// Finish the current stage. Time spent in a stage that runs more than
+    /// once (e.g. reset and probe retries) is accumulated.
+    fn end(&mut self, bytes: Option<u64>) {
+        let Some((stage, started)) = self.current.take() else {
+            return;
+        };
+
+        let elapsed = started.elapsed();
+        match self.finished.iter_mut().find(|(s, _, _)| *s == stage) {
+            Some((_, total, _)) => *total += elapsed,
+            None => self.finished.push((stage, elapsed, bytes)),
+        }
+    }
+
+    fn summary(&self) -> String {
+        let mut summary = String::new();
+        summary.push_str("| Stage | Elapsed | Throughput |\n");
+        summary.push_str("| ----- | ------- | ---------- |\n");
+        for (stage, elapsed, bytes) in &self.finished {
+            let throughput = match bytes {
+                Some(bytes) if !elapsed.is_zero() => format!(
+                    "{:.1} KiB/s",
+                    *bytes as f64 / 1024.0 / elapsed.as_secs_f64()
+                ),
+                _ => "-".to_string(),
+            };
+            summary.push_str(&format!(
+                "| {} | {:.3}s | {} |\n",
+                stage.name(),
+                elapsed.as_secs_f64(),
+                throughput
+            ));
+        }
+        summary
     }
 }
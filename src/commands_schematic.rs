@@ -1,8 +1,8 @@
 use std::{collections::BTreeMap, fmt::Debug};
 
 use rmcp::{
-    Error as McpError,
-    handler::server::tool::Parameters,
+    ErrorData as McpError,
+    handler::server::wrapper::Parameters,
     model::{CallToolResult, Content},
     schemars, tool, tool_router,
 };
@@ -1,37 +1,293 @@
 use std::{
-    fmt::Debug,
-    io,
+    fmt::{self, Debug},
+    io::{self, Write},
+    path::PathBuf,
     thread::sleep,
     time::{Duration, Instant},
 };
 
+use base64::{Engine, prelude::BASE64_STANDARD};
+use regex::Regex;
 use rmcp::{
-    Error as McpError,
-    handler::server::tool::Parameters,
+    ErrorData as McpError,
+    handler::server::wrapper::Parameters,
     model::{CallToolResult, Content},
     schemars, tool, tool_router,
 };
 use serde::Deserialize;
-use serialport::{SerialPort, available_ports};
+use serialport::SerialPort;
 
 use crate::{
     commands::Commands,
-    manifest::{SerialOptions, SerialResetMethod},
+    manifest::{FlowControl, LineEnding, Parity, ResetStep, SerialOptions, SerialResetMethod},
+    port_lease::canonical_port,
+    serial_crash::{Symbolizer, analyze_crashes},
+    serial_dictionary::{DictionaryDecoder, LogDictionary},
+    serial_format::{LineSplitter, ReadFormat, TextDecoder, hex_dump, strip_ansi},
+    serial_log::{LogFilter, LogLevel, LogRecord, LogSummary},
+    serial_network::{NetworkPort, is_network_port},
+    serial_ports::list_ports,
+    serial_recorder::SerialRecorder,
 };
 
 #[derive(Debug, Deserialize, schemars::JsonSchema)]
 pub struct SerialResetRequest {
-    #[schemars(description = "The port to use")]
+    #[schemars(description = "The port to use, or its alias")]
     pub port: String,
+
+    #[schemars(
+        description = "Name of the reset sequence to run, e.g. to enter the download mode, \
+            defaults to the reset method configured in the manifest"
+    )]
+    pub sequence: Option<String>,
 }
 
 #[derive(Debug, Deserialize, schemars::JsonSchema)]
 pub struct SerialReadRequest {
-    #[schemars(description = "The port to use")]
+    #[schemars(description = "The port to use, or its alias")]
     pub port: String,
 
     #[schemars(description = "Duration in milliseconds to read, defaults to 10000")]
     pub timeout: Option<u32>,
+
+    #[schemars(
+        description = "Whether to reset the device before reading, defaults to true. Set to \
+            false to read logs from the running device."
+    )]
+    pub reset: Option<bool>,
+
+    #[schemars(
+        description = "Regular expression to stop reading at, once a line (or an unterminated \
+            prompt) matches it"
+    )]
+    pub until: Option<String>,
+
+    #[schemars(description = "Stop reading once this many lines are read")]
+    pub max_lines: Option<usize>,
+
+    #[schemars(description = "Stop reading once no data is received for this many milliseconds")]
+    pub idle_ms: Option<u32>,
+
+    #[schemars(
+        description = "Whether to record the raw data and the timestamped lines read to \
+            files under the project, defaults to false"
+    )]
+    pub record: Option<bool>,
+
+    #[schemars(
+        description = "Format of the data returned: `text` for timestamped lines, `hex` for \
+            a hex dump of the raw bytes, or `base64`. Defaults to `text`."
+    )]
+    pub format: Option<ReadFormat>,
+
+    #[schemars(
+        description = "Path to the firmware ELF to resolve the addresses of crash dumps \
+            against, defaults to the one configured in the manifest"
+    )]
+    pub elf: Option<String>,
+
+    #[schemars(
+        description = "Path to the `log_dictionary.json` generated by the Zephyr build, to \
+            decode the binary output of dictionary based logging into log lines, defaults to \
+            the one configured in the manifest"
+    )]
+    pub log_dictionary: Option<String>,
+
+    #[serde(flatten)]
+    pub log: SerialLogRequest,
+
+    #[serde(flatten)]
+    pub text: SerialTextRequest,
+
+    #[serde(flatten)]
+    pub line: SerialLineRequest,
+}
+
+#[derive(Debug, Deserialize, schemars::JsonSchema)]
+pub struct SerialWriteRequest {
+    #[schemars(description = "The port to use, or its alias")]
+    pub port: String,
+
+    #[schemars(
+        description = "The data to write, either text or hex encoded bytes (e.g. \"01 02 ff\") if `hex` is true"
+    )]
+    pub data: String,
+
+    #[schemars(description = "Whether `data` is hex encoded bytes, defaults to false")]
+    pub hex: Option<bool>,
+
+    #[schemars(
+        description = "Line ending appended to text data, defaults to the one configured in the manifest"
+    )]
+    pub line_ending: Option<LineEnding>,
+
+    #[serde(flatten)]
+    pub line: SerialLineRequest,
+}
+
+#[derive(Debug, Deserialize, schemars::JsonSchema)]
+pub struct SerialSendExpectRequest {
+    #[schemars(description = "The port to use, or its alias")]
+    pub port: String,
+
+    #[schemars(
+        description = "The data to write, either text or hex encoded bytes (e.g. \"01 02 ff\") if `hex` is true"
+    )]
+    pub data: String,
+
+    #[schemars(description = "Whether `data` is hex encoded bytes, defaults to false")]
+    pub hex: Option<bool>,
+
+    #[schemars(
+        description = "Line ending appended to text data, defaults to the one configured in the manifest"
+    )]
+    pub line_ending: Option<LineEnding>,
+
+    #[schemars(description = "Regular expression to wait for in the output of the device")]
+    pub expect: String,
+
+    #[schemars(description = "Duration in milliseconds to wait for the match, defaults to 5000")]
+    pub timeout: Option<u32>,
+
+    #[serde(flatten)]
+    pub text: SerialTextRequest,
+
+    #[serde(flatten)]
+    pub line: SerialLineRequest,
+}
+
+/// Per-call overrides of the text decoding configured in the manifest
+#[derive(Debug, Default, Deserialize, schemars::JsonSchema)]
+pub struct SerialTextRequest {
+    #[schemars(
+        description = "Text encoding of the output of the device, e.g. `utf-8` or `gbk`, \
+            defaults to the one configured in the manifest"
+    )]
+    pub encoding: Option<String>,
+
+    #[schemars(
+        description = "Whether to strip ANSI escape sequences (e.g. colors) from the output, \
+            defaults to the one configured in the manifest"
+    )]
+    pub strip_ansi: Option<bool>,
+}
+
+/// Filters and summary of the log lines returned as text
+#[derive(Debug, Default, Deserialize, schemars::JsonSchema)]
+pub struct SerialLogRequest {
+    #[schemars(
+        description = "Least severe log level to return: `error`, `warning`, `info`, `debug` \
+            or `verbose`. Lines without a recognized level are always returned."
+    )]
+    pub min_level: Option<LogLevel>,
+
+    #[schemars(description = "Only return log lines of these modules (or ESP-IDF tags)")]
+    pub modules: Option<Vec<String>>,
+
+    #[schemars(description = "Leave out log lines of these modules (or ESP-IDF tags)")]
+    pub exclude_modules: Option<Vec<String>>,
+
+    #[schemars(description = "Regular expression lines must match to be returned")]
+    pub filter: Option<String>,
+
+    #[schemars(
+        description = "Whether to prepend a summary with the number of lines per log level \
+            and the first error, defaults to false"
+    )]
+    pub summary: Option<bool>,
+}
+
+impl SerialLogRequest {
+    pub fn filter(self) -> Result<LogFilter, McpError> {
+        let pattern = self
+            .filter
+            .map(|filter| Regex::new(&filter))
+            .transpose()
+            .map_err(|e| {
+                McpError::invalid_params(format!("Invalid regular expression: {}", e), None)
+            })?;
+
+        Ok(LogFilter {
+            min_level: self.min_level,
+            modules: self.modules.unwrap_or_default(),
+            exclude_modules: self.exclude_modules.unwrap_or_default(),
+            pattern,
+        })
+    }
+}
+
+/// Per-call overrides of the line parameters configured in the manifest
+#[derive(Debug, Default, Deserialize, schemars::JsonSchema)]
+pub struct SerialLineRequest {
+    #[schemars(
+        description = "Baud rate, defaults to the one set by `serial_set_baudrate` or \
+            configured in the manifest"
+    )]
+    pub baudrate: Option<u32>,
+
+    #[schemars(
+        description = "Number of data bits, from 5 to 8, defaults to the one configured \
+            in the manifest"
+    )]
+    pub data_bits: Option<u8>,
+
+    #[schemars(description = "Parity checking, defaults to the one configured in the manifest")]
+    pub parity: Option<Parity>,
+
+    #[schemars(
+        description = "Number of stop bits, 1 or 2, defaults to the one configured in the manifest"
+    )]
+    pub stop_bits: Option<u8>,
+
+    #[schemars(description = "Flow control, defaults to the one configured in the manifest")]
+    pub flow_control: Option<FlowControl>,
+}
+
+#[derive(Debug, Deserialize, schemars::JsonSchema)]
+pub struct SerialSetBaudrateRequest {
+    #[schemars(description = "The port to use, or its alias")]
+    pub port: String,
+
+    #[schemars(description = "The new baud rate")]
+    pub baudrate: u32,
+}
+
+#[derive(Debug, Deserialize, schemars::JsonSchema)]
+pub struct SerialCaptureRequest {
+    #[schemars(description = "The ports to capture from, each with its own settings")]
+    pub ports: Vec<SerialCapturePort>,
+
+    #[schemars(description = "Duration in milliseconds to capture, defaults to 10000")]
+    pub timeout: Option<u32>,
+
+    #[serde(flatten)]
+    pub text: SerialTextRequest,
+}
+
+#[derive(Debug, Deserialize, schemars::JsonSchema)]
+pub struct SerialCapturePort {
+    #[schemars(description = "The port to capture from, or its alias")]
+    pub port: String,
+
+    #[schemars(
+        description = "Whether to reset the device through this port before capturing: \
+            `true` for the reset method configured, or a reset method for this port, \
+            i.e. \"dtr\", \"rts\" or the name of a reset sequence. Defaults to false."
+    )]
+    pub reset: Option<SerialCaptureReset>,
+
+    #[serde(flatten)]
+    pub line: SerialLineRequest,
+}
+
+#[derive(Debug, Deserialize, schemars::JsonSchema)]
+#[serde(untagged)]
+pub enum SerialCaptureReset {
+    /// Whether to reset with the reset method configured
+    Enabled(bool),
+
+    /// Reset method of the port
+    Method(SerialResetMethod),
 }
 
 #[tool_router(router = serial_router, vis = "pub")]
@@ -42,24 +298,11 @@ impl Commands {
         annotations(read_only_hint = true)
     )]
     async fn serial_list_ports(&self) -> Result<CallToolResult, McpError> {
-        let ports = available_ports()
-            .map_err(|e| McpError::internal_error(format!("Failed to list ports: {}", e), None))
-            .map(|ports| {
-                ports
-                    .into_iter()
-                    .filter_map(|port| {
-                        if cfg!(target_os = "macos") && !port.port_name.starts_with("/dev/cu.") {
-                            return None;
-                        }
-
-                        if let serialport::SerialPortType::UsbPort(_) = port.port_type {
-                            Some(port.port_name)
-                        } else {
-                            None
-                        }
-                    })
-                    .collect::<Vec<_>>()
-            })?;
+        let ports = list_ports()
+            .map_err(|e| McpError::internal_error(format!("Failed to list ports: {}", e), None))?
+            .iter()
+            .map(|port| port.describe(&self.port_aliases(port)))
+            .collect::<Vec<_>>();
 
         Ok(CallToolResult::success(vec![Content::text(
             ports.join("\n"),
@@ -72,26 +315,29 @@ impl Commands {
     )]
     async fn serial_reset(
         &self,
-        Parameters(SerialResetRequest { port }): Parameters<SerialResetRequest>,
+        Parameters(SerialResetRequest { port, sequence }): Parameters<SerialResetRequest>,
     ) -> Result<rmcp::model::CallToolResult, McpError> {
-        let serial_opts = self
-            .manifest
-            .serial
-            .as_ref()
-            .ok_or(McpError::invalid_params(
-                "Serial options are not defined in the manifest".to_string(),
-                None,
-            ))?;
+        let port = self.resolve_port(&port)?;
 
-        let mut device = serial_open(&port, serial_opts)
+        let serial_opts = SerialOptions::from(self)?;
+
+        let _lease = self.leases.acquire(&port, "serial_reset")?;
+        let line = self.serial_line(&port, &SerialLineRequest::default())?;
+        let mut device = serial_open(&port, &line)
             .map_err(|e| McpError::internal_error(format!("Failed to open device: {}", e), None))?;
 
-        serial_reset(&mut device, serial_opts).map_err(|e| {
+        self.logger
+            .info("serial", format!("Opened {} at {}", port, line));
+
+        serial_reset(&mut device, serial_opts, sequence.as_deref()).map_err(|e| {
             McpError::internal_error(format!("Failed to reset device: {}", e), None)
         })?;
 
         Ok(CallToolResult::success(vec![Content::text(
-            "Device reset command executed successfully.".to_string(),
+            match sequence {
+                Some(sequence) => format!("Reset sequence '{}' executed successfully.", sequence),
+                None => "Device reset command executed successfully.".to_string(),
+            },
         )]))
     }
 
@@ -101,23 +347,59 @@ impl Commands {
     )]
     async fn serial_read(
         &self,
-        Parameters(SerialReadRequest { port, timeout }): Parameters<SerialReadRequest>,
+        Parameters(SerialReadRequest {
+            port,
+            timeout,
+            reset,
+            until,
+            max_lines,
+            idle_ms,
+            record,
+            format,
+            elf,
+            log_dictionary,
+            log,
+            text,
+            line,
+        }): Parameters<SerialReadRequest>,
     ) -> Result<rmcp::model::CallToolResult, McpError> {
-        let serial_opts = self
-            .manifest
-            .serial
-            .as_ref()
-            .ok_or(McpError::invalid_params(
-                "Serial options are not defined in the manifest".to_string(),
-                None,
-            ))?;
+        let port = self.resolve_port(&port)?;
+
+        let serial_opts = SerialOptions::from(self)?;
+
+        let until = until
+            .map(|until| Regex::new(&until))
+            .transpose()
+            .map_err(|e| {
+                McpError::invalid_params(format!("Invalid regular expression: {}", e), None)
+            })?;
+
+        let summary = log.summary.unwrap_or(false);
+        let filter = log.filter()?;
+
+        let format = format.unwrap_or_default();
+        let decoder = self.serial_decoder(&text)?;
+        let symbolizer = self.serial_symbolizer(elf)?;
+        let mut dictionary = self.serial_dictionary(log_dictionary)?;
 
-        let mut device = serial_open(&port, serial_opts)
+        let _lease = self.leases.acquire(&port, "serial_read")?;
+        let line = self.serial_line(&port, &line)?;
+        let mut device = serial_open(&port, &line)
             .map_err(|e| McpError::internal_error(format!("Failed to open device: {}", e), None))?;
 
-        serial_reset(&mut device, serial_opts).map_err(|e| {
-            McpError::internal_error(format!("Failed to reset device: {}", e), None)
-        })?;
+        self.logger
+            .info("serial", format!("Opened {} at {}", port, line));
+
+        let mut recorder = match record {
+            Some(true) => Some(self.serial_recorder(&port)?),
+            _ => None,
+        };
+
+        if reset.unwrap_or(true) {
+            serial_reset(&mut device, serial_opts, None).map_err(|e| {
+                McpError::internal_error(format!("Failed to reset device: {}", e), None)
+            })?;
+        }
 
         device
             .set_timeout(Duration::from_millis(100))
@@ -126,20 +408,66 @@ impl Commands {
         let start_time = Instant::now();
         let timeout = timeout.map_or(Duration::from_secs(10), |t| Duration::from_millis(t as u64));
 
+        let idle = idle_ms.map(|t| Duration::from_millis(t as u64));
+
         let mut lines = Vec::<(Instant, String)>::new();
-        let mut last_read = String::new();
+        let mut received = Vec::<u8>::new();
+        let mut splitter = LineSplitter::new(decoder);
+        let mut last_data = start_time;
+        let mut stop_reason = StopReason::Timeout;
+
+        'read: while Instant::now().duration_since(start_time) < timeout {
+            if let Some(idle) = idle
+                && Instant::now().duration_since(last_data) >= idle
+            {
+                stop_reason = StopReason::Idle;
+                break;
+            }
 
-        while Instant::now().duration_since(start_time) < timeout {
             let mut buffer = vec![0; 1024];
             match device.read(&mut buffer) {
                 Ok(bytes) if bytes > 0 => {
-                    let content = String::from_utf8_lossy(&buffer[..bytes.min(buffer.len())]);
-                    last_read.push_str(&content);
+                    last_data = Instant::now();
 
-                    while let Some(newline_pos) = last_read.find('\n') {
-                        let line = last_read[..newline_pos].trim_end_matches('\r').to_string();
+                    if let Some(recorder) = &mut recorder {
+                        recorder.raw(&buffer[..bytes]);
+                    }
+
+                    if format != ReadFormat::Text {
+                        received.extend_from_slice(&buffer[..bytes]);
+                    }
+
+                    let decoded = match &mut dictionary {
+                        Some(dictionary) => dictionary.feed(&buffer[..bytes]),
+                        None => splitter.feed(&buffer[..bytes]),
+                    };
+
+                    for line in decoded {
+                        if let Some(recorder) = &mut recorder {
+                            recorder.line(&line);
+                        }
+
+                        let matched = until.as_ref().is_some_and(|until| until.is_match(&line));
                         lines.push((Instant::now(), line));
-                        last_read = last_read[newline_pos + 1..].to_string();
+
+                        if matched {
+                            stop_reason = StopReason::Until;
+                            break 'read;
+                        }
+
+                        if max_lines.is_some_and(|max_lines| lines.len() >= max_lines) {
+                            stop_reason = StopReason::MaxLines;
+                            break 'read;
+                        }
+                    }
+
+                    // Prompts like `uart:~$ ` are not terminated by newlines
+                    if until
+                        .as_ref()
+                        .is_some_and(|until| until.is_match(&splitter.partial()))
+                    {
+                        stop_reason = StopReason::Until;
+                        break;
                     }
                 }
                 Ok(_) => continue, // No data read, continue
@@ -152,56 +480,870 @@ impl Commands {
                 }
             }
         }
-        if !last_read.is_empty() {
-            let final_line = last_read
-                .trim_end_matches('\r')
-                .trim_end_matches('\n')
-                .to_string();
+        let remaining = match &mut dictionary {
+            Some(dictionary) => dictionary.finish(),
+            None => splitter.finish().into_iter().collect(),
+        };
+        for final_line in remaining {
+            if let Some(recorder) = &mut recorder {
+                recorder.line(&final_line);
+            }
             lines.push((Instant::now(), final_line));
         }
 
         let mut response = String::new();
-        if lines.is_empty() {
-            response.push_str("No data read from the device.\n");
+        match format {
+            _ if lines.is_empty() => {
+                response.push_str("No data read from the device.\n");
+            }
+            ReadFormat::Text => {
+                let elapsed = |timestamp: &Instant| timestamp.duration_since(start_time);
+                // Colors are left out whether or not they are kept in the
+                // output, so that colored logs are parsed the same
+                let plain = lines
+                    .iter()
+                    .map(|(_, line)| strip_ansi(line))
+                    .collect::<Vec<_>>();
+                let records = plain
+                    .iter()
+                    .map(|line| LogRecord::parse(line))
+                    .collect::<Vec<_>>();
+                let shown = lines
+                    .iter()
+                    .zip(&records)
+                    .filter(|((_, line), record)| filter.matches(line, record))
+                    .map(|(line, _)| line)
+                    .collect::<Vec<_>>();
+
+                if summary {
+                    let mut log_summary = LogSummary::default();
+                    for (i, record) in records.iter().enumerate() {
+                        log_summary.add(i, record);
+                    }
+
+                    response.push_str(&format!(
+                        "Summary of the {} lines read: {}.\n",
+                        lines.len(),
+                        log_summary
+                    ));
+                    if let Some(i) = log_summary.first_error {
+                        let record = &records[i];
+                        response.push_str(&format!(
+                            "First error at [{:.3}]{}: {}{}\n",
+                            elapsed(&lines[i].0).as_secs_f64(),
+                            record
+                                .timestamp
+                                .map(|timestamp| format!(" (device time {})", timestamp))
+                                .unwrap_or_default(),
+                            record
+                                .module
+                                .map(|module| format!("{}: ", module))
+                                .unwrap_or_default(),
+                            record.message
+                        ));
+                    }
+                    response.push('\n');
+                }
+
+                if !filter.is_empty() {
+                    response.push_str(&format!(
+                        "{} of {} lines match the filters.\n\n",
+                        shown.len(),
+                        lines.len()
+                    ));
+                }
+
+                if !shown.is_empty() {
+                    response.push_str(
+                        "Logs read from the device, formatted as `[seconds]: message`:\n\n",
+                    );
+                    response.push_str("```\n");
+                    for (timestamp, line) in shown {
+                        response.push_str(&format!(
+                            "[{:.3}]: {}\n",
+                            elapsed(timestamp).as_secs_f64(),
+                            line
+                        ));
+                    }
+                    response.push_str("```\n");
+                }
+            }
+            ReadFormat::Hex => {
+                response.push_str(&format!(
+                    "{} bytes read from the device, as a hex dump:\n\n```\n{}```\n",
+                    received.len(),
+                    hex_dump(&received)
+                ));
+            }
+            ReadFormat::Base64 => {
+                response.push_str(&format!(
+                    "{} bytes read from the device, base64 encoded:\n\n```\n{}\n```\n",
+                    received.len(),
+                    BASE64_STANDARD.encode(&received)
+                ));
+            }
+        }
+
+        response.push_str(&format!(
+            "\nStopped reading after {:.3}s: {}",
+            Instant::now().duration_since(start_time).as_secs_f64(),
+            stop_reason
+        ));
+
+        if let Some(symbolizer) = &symbolizer {
+            let lines = lines.iter().map(|(_, line)| line).collect::<Vec<_>>();
+            if let Some(analysis) = analyze_crashes(symbolizer, &lines) {
+                response.push_str("\n\n");
+                response.push_str(analysis.trim_end());
+            }
+        }
+
+        if let Some(mut recorder) = recorder {
+            recorder.flush();
+            response.push_str(&recorded_files(&recorder));
+        }
+
+        Ok(CallToolResult::success(vec![Content::text(response)]))
+    }
+
+    #[tool(
+        name = "serial_capture",
+        description = "Read data from multiple serial ports at once, e.g. from the \
+            different chips of a board, and merge the lines received into a single \
+            time-ordered log, each tagged with its port."
+    )]
+    async fn serial_capture(
+        &self,
+        Parameters(SerialCaptureRequest {
+            ports,
+            timeout,
+            text,
+        }): Parameters<SerialCaptureRequest>,
+    ) -> Result<CallToolResult, McpError> {
+        let serial_opts = SerialOptions::from(self)?;
+
+        if ports.is_empty() {
+            return Err(McpError::invalid_params(
+                "At least one port is required".to_string(),
+                None,
+            ));
+        }
+
+        let decoder = self.serial_decoder(&text)?;
+
+        // Open all ports first, so that none starts capturing if any fails
+        let mut devices = Vec::new();
+        let mut leases = Vec::new();
+        for SerialCapturePort { port, reset, line } in ports {
+            let path = self.resolve_port(&port)?;
+            let lease = self.leases.acquire(&path, "serial_capture")?;
+            let line = self.serial_line(&path, &line)?;
+            let device = serial_open(&path, &line).map_err(|e| {
+                McpError::internal_error(format!("Failed to open {}: {}", port, e), None)
+            })?;
+
+            self.logger
+                .info("serial", format!("Opened {} at {}", path, line));
+
+            let reset = match reset {
+                None | Some(SerialCaptureReset::Enabled(false)) => None,
+                Some(SerialCaptureReset::Enabled(true)) => serial_opts.reset.clone(),
+                Some(SerialCaptureReset::Method(method)) => Some(method),
+            };
+            if let Some(method) = &reset {
+                reset_steps(method, serial_opts).map_err(|e| {
+                    McpError::invalid_params(format!("Invalid reset of {}: {}", port, e), None)
+                })?;
+            }
+
+            // Tag lines with the port as requested, which may be an alias
+            devices.push((port, device, reset));
+            leases.push(lease);
+        }
+
+        let start_time = Instant::now();
+        let timeout = timeout.map_or(Duration::from_secs(10), |t| Duration::from_millis(t as u64));
+
+        let handles = devices
+            .into_iter()
+            .map(|(port, mut device, reset)| {
+                let serial_opts = serial_opts.clone();
+                std::thread::spawn(move || {
+                    let lines = capture_lines(&mut device, &serial_opts, reset, decoder, || {
+                        Instant::now().duration_since(start_time) < timeout
+                    });
+                    (port, lines)
+                })
+            })
+            .collect::<Vec<_>>();
+
+        let mut merged = Vec::<(Instant, String, String)>::new();
+        let mut errors = Vec::new();
+        for handle in handles {
+            let (port, (lines, error)) = handle
+                .join()
+                .map_err(|_| McpError::internal_error("Capture thread panicked", None))?;
+
+            if let Some(error) = error {
+                errors.push(format!("{}: {}", port, error));
+            }
+            merged.extend(
+                lines
+                    .into_iter()
+                    .map(|(time, line)| (time, port.clone(), line)),
+            );
+        }
+
+        drop(leases);
+
+        // Stable sort, so lines received at the same instant keep their order
+        merged.sort_by_key(|(time, _, _)| *time);
+
+        let mut response = String::new();
+        if merged.is_empty() {
+            response.push_str("No data read from the devices.\n");
         } else {
-            response.push_str("Logs read from the device, formatted as `[seconds]: message`:\n\n");
+            response.push_str(
+                "Logs read from the devices, formatted as `[seconds] port: message`:\n\n",
+            );
             response.push_str("```\n");
-            for (timestamp, line) in lines {
-                let elapsed = timestamp.duration_since(start_time);
-                response.push_str(&format!("[{:.3}]: {}\n", elapsed.as_secs_f64(), line));
+            for (time, port, line) in merged {
+                let elapsed = time.duration_since(start_time);
+                response.push_str(&format!(
+                    "[{:.3}] {}: {}\n",
+                    elapsed.as_secs_f64(),
+                    port,
+                    line
+                ));
             }
-            response.push_str("```");
+            response.push_str("```\n");
+        }
+
+        for error in errors {
+            response.push_str(&format!("\nCapture stopped with error on {}", error));
+        }
+
+        Ok(CallToolResult::success(vec![Content::text(response)]))
+    }
+
+    #[tool(
+        name = "serial_set_baudrate",
+        description = "Set the baud rate used for the specified serial port by the \
+            following calls, e.g. when the firmware switches speed after boot. Also \
+            applies to the monitor of the port if it is running."
+    )]
+    async fn serial_set_baudrate(
+        &self,
+        Parameters(SerialSetBaudrateRequest { port, baudrate }): Parameters<
+            SerialSetBaudrateRequest,
+        >,
+    ) -> Result<CallToolResult, McpError> {
+        let port = self.resolve_port(&port)?;
+
+        if baudrate == 0 {
+            return Err(McpError::invalid_params(
+                "Baud rate must be greater than 0".to_string(),
+                None,
+            ));
+        }
+
+        self.baudrates
+            .lock()
+            .unwrap()
+            .insert(canonical_port(&port), baudrate);
+
+        self.logger.info(
+            "serial",
+            format!("Set baud rate of {} to {}", port, baudrate),
+        );
+
+        let mut response = format!("Baud rate of {} set to {}.", port, baudrate);
+        if self
+            .monitors
+            .with(&port, |monitor| monitor.set_baudrate(baudrate))
+            .is_some()
+        {
+            response.push_str(" Applied to the running monitor.");
         }
 
         Ok(CallToolResult::success(vec![Content::text(response)]))
     }
+
+    #[tool(
+        name = "serial_write",
+        description = "Write text or bytes to the connected device via specified serial port"
+    )]
+    async fn serial_write(
+        &self,
+        Parameters(SerialWriteRequest {
+            port,
+            data,
+            hex,
+            line_ending,
+            line,
+        }): Parameters<SerialWriteRequest>,
+    ) -> Result<CallToolResult, McpError> {
+        let port = self.resolve_port(&port)?;
+
+        let serial_opts = SerialOptions::from(self)?;
+
+        let bytes = serial_payload(&data, hex, line_ending, serial_opts)?;
+
+        let _lease = self.leases.acquire(&port, "serial_write")?;
+        let line = self.serial_line(&port, &line)?;
+        let mut device = serial_open(&port, &line)
+            .map_err(|e| McpError::internal_error(format!("Failed to open device: {}", e), None))?;
+
+        self.logger
+            .info("serial", format!("Opened {} at {}", port, line));
+
+        serial_write(&mut device, &bytes).map_err(|e| {
+            McpError::internal_error(format!("Failed to write to device: {}", e), None)
+        })?;
+
+        Ok(CallToolResult::success(vec![Content::text(format!(
+            "Wrote {} bytes to the device.",
+            bytes.len()
+        ))]))
+    }
+
+    #[tool(
+        name = "serial_send_expect",
+        description = "Write text or bytes to the connected device via specified serial \
+            port, then read from the device until the output matches the expected \
+            regular expression or the timeout expires. Useful for interacting with \
+            shells and AT command interfaces."
+    )]
+    async fn serial_send_expect(
+        &self,
+        Parameters(SerialSendExpectRequest {
+            port,
+            data,
+            hex,
+            line_ending,
+            expect,
+            timeout,
+            text,
+            line,
+        }): Parameters<SerialSendExpectRequest>,
+    ) -> Result<CallToolResult, McpError> {
+        let port = self.resolve_port(&port)?;
+
+        let serial_opts = SerialOptions::from(self)?;
+
+        let bytes = serial_payload(&data, hex, line_ending, serial_opts)?;
+        let decoder = self.serial_decoder(&text)?;
+
+        let expect = Regex::new(&expect).map_err(|e| {
+            McpError::invalid_params(format!("Invalid regular expression: {}", e), None)
+        })?;
+
+        let _lease = self.leases.acquire(&port, "serial_send_expect")?;
+        let line = self.serial_line(&port, &line)?;
+        let mut device = serial_open(&port, &line)
+            .map_err(|e| McpError::internal_error(format!("Failed to open device: {}", e), None))?;
+
+        self.logger
+            .info("serial", format!("Opened {} at {}", port, line));
+
+        device
+            .set_timeout(Duration::from_millis(100))
+            .map_err(|e| McpError::internal_error(format!("Failed to set timeout: {}", e), None))?;
+
+        device
+            .clear(serialport::ClearBuffer::Input)
+            .map_err(|e| McpError::internal_error(format!("Failed to clear input: {}", e), None))?;
+
+        serial_write(&mut device, &bytes).map_err(|e| {
+            McpError::internal_error(format!("Failed to write to device: {}", e), None)
+        })?;
+
+        let start_time = Instant::now();
+        let timeout = timeout.map_or(Duration::from_secs(5), |t| Duration::from_millis(t as u64));
+
+        let mut received = Vec::<u8>::new();
+        let mut captures = None;
+
+        while captures.is_none() && Instant::now().duration_since(start_time) < timeout {
+            let mut buffer = vec![0; 1024];
+            match device.read(&mut buffer) {
+                Ok(bytes) if bytes > 0 => {
+                    received.extend_from_slice(&buffer[..bytes]);
+                    let output = decoder.decode(&received);
+                    captures = expect.captures(&output).map(|caps| {
+                        caps.iter()
+                            .map(|m| m.map(|m| m.as_str().to_string()))
+                            .collect::<Vec<_>>()
+                    });
+                }
+                Ok(_) => continue, // No data read, continue
+                Err(e) if e.kind() == io::ErrorKind::TimedOut => continue,
+                Err(e) => {
+                    return Err(McpError::internal_error(
+                        format!("Failed to read from device: {}", e),
+                        None,
+                    ));
+                }
+            }
+        }
+
+        let elapsed = Instant::now().duration_since(start_time);
+        let output = decoder.decode(&received);
+
+        let mut response = String::new();
+        match &captures {
+            Some(groups) => {
+                response.push_str(&format!("Matched after {:.3}s.\n\n", elapsed.as_secs_f64()));
+                response.push_str("## Captures\n\n");
+                response.push_str("| Group | Name | Value |\n");
+                response.push_str("| ----- | ---- | ----- |\n");
+                for ((index, value), name) in groups.iter().enumerate().zip(expect.capture_names())
+                {
+                    response.push_str(&format!(
+                        "| {} | {} | {} |\n",
+                        index,
+                        name.unwrap_or("-"),
+                        match value {
+                            Some(value) => format!("`{}`", value.trim_end()),
+                            None => "-".to_string(),
+                        }
+                    ));
+                }
+                response.push('\n');
+            }
+            None => {
+                response.push_str(&format!(
+                    "No match for `{}` within {}ms.\n\n",
+                    expect.as_str(),
+                    timeout.as_millis()
+                ));
+            }
+        }
+
+        if output.is_empty() {
+            response.push_str("## Output\n\nNo data read from the device.\n");
+        } else {
+            response.push_str(&format!("## Output\n\n```\n{}\n```\n", output.trim_end()));
+        }
+
+        if captures.is_some() {
+            Ok(CallToolResult::success(vec![Content::text(response)]))
+        } else {
+            Ok(CallToolResult::error(vec![Content::text(response)]))
+        }
+    }
+}
+
+/// Read lines from the device while `running` returns true, optionally
+/// resetting it first. Returns the lines with the time they were received,
+/// and the error which stopped the reading, if any.
+fn capture_lines(
+    device: &mut Box<dyn SerialPort>,
+    serial_opts: &SerialOptions,
+    reset: Option<SerialResetMethod>,
+    decoder: TextDecoder,
+    running: impl Fn() -> bool,
+) -> (Vec<(Instant, String)>, Option<String>) {
+    let mut lines = Vec::new();
+
+    if let Some(method) = reset
+        && let Err(e) = serial_reset_with(device, &method, serial_opts)
+    {
+        return (lines, Some(format!("Failed to reset device: {}", e)));
+    }
+
+    if let Err(e) = device.set_timeout(Duration::from_millis(100)) {
+        return (lines, Some(format!("Failed to set timeout: {}", e)));
+    }
+
+    let mut splitter = LineSplitter::new(decoder);
+    let mut buffer = vec![0; 1024];
+    let mut error = None;
+
+    while running() {
+        match device.read(&mut buffer) {
+            Ok(bytes) if bytes > 0 => {
+                let now = Instant::now();
+                lines.extend(
+                    splitter
+                        .feed(&buffer[..bytes])
+                        .into_iter()
+                        .map(|line| (now, line)),
+                );
+            }
+            Ok(_) => continue, // No data read, continue
+            Err(e) if e.kind() == io::ErrorKind::TimedOut => continue,
+            Err(e) => {
+                error = Some(format!("Failed to read from device: {}", e));
+                break;
+            }
+        }
+    }
+
+    if let Some(line) = splitter.finish() {
+        lines.push((Instant::now(), line));
+    }
+
+    (lines, error)
+}
+
+/// Condition that ended a `serial_read`
+enum StopReason {
+    Timeout,
+    Until,
+    MaxLines,
+    Idle,
+}
+
+impl fmt::Display for StopReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            StopReason::Timeout => "timeout expired",
+            StopReason::Until => "`until` pattern matched",
+            StopReason::MaxLines => "`max_lines` reached",
+            StopReason::Idle => "no data received within `idle_ms`",
+        })
+    }
+}
+
+/// Line parameters to open a serial port with
+#[derive(Debug, Clone, Copy)]
+pub struct SerialLine {
+    pub baudrate: u32,
+    pub data_bits: serialport::DataBits,
+    pub parity: serialport::Parity,
+    pub stop_bits: serialport::StopBits,
+    pub flow_control: serialport::FlowControl,
+}
+
+impl fmt::Display for SerialLine {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let data_bits = match self.data_bits {
+            serialport::DataBits::Five => 5,
+            serialport::DataBits::Six => 6,
+            serialport::DataBits::Seven => 7,
+            serialport::DataBits::Eight => 8,
+        };
+        let parity = match self.parity {
+            serialport::Parity::None => 'N',
+            serialport::Parity::Odd => 'O',
+            serialport::Parity::Even => 'E',
+        };
+        let stop_bits = match self.stop_bits {
+            serialport::StopBits::One => 1,
+            serialport::StopBits::Two => 2,
+        };
+
+        write!(
+            f,
+            "{} baud {}{}{}",
+            self.baudrate, data_bits, parity, stop_bits
+        )?;
+
+        match self.flow_control {
+            serialport::FlowControl::None => Ok(()),
+            serialport::FlowControl::Software => write!(f, " with XON/XOFF"),
+            serialport::FlowControl::Hardware => write!(f, " with RTS/CTS"),
+        }
+    }
 }
 
-fn serial_open(path: &str, opts: &SerialOptions) -> serialport::Result<Box<dyn SerialPort>> {
-    serialport::new(path, opts.baudrate)
-        .flow_control(serialport::FlowControl::None)
+impl Commands {
+    /// Line parameters of the port, from the manifest with the baud rate set
+    /// by `serial_set_baudrate` and the per-call overrides applied.
+    pub fn serial_line(
+        &self,
+        port: &str,
+        overrides: &SerialLineRequest,
+    ) -> Result<SerialLine, McpError> {
+        let serial_opts = SerialOptions::from(self)?;
+
+        let baudrate = overrides
+            .baudrate
+            .or_else(|| {
+                self.baudrates
+                    .lock()
+                    .unwrap()
+                    .get(&canonical_port(port))
+                    .copied()
+            })
+            .unwrap_or(serial_opts.baudrate);
+
+        let data_bits = match overrides.data_bits.unwrap_or(serial_opts.data_bits) {
+            5 => serialport::DataBits::Five,
+            6 => serialport::DataBits::Six,
+            7 => serialport::DataBits::Seven,
+            8 => serialport::DataBits::Eight,
+            bits => {
+                return Err(McpError::invalid_params(
+                    format!("Invalid number of data bits: {}", bits),
+                    None,
+                ));
+            }
+        };
+
+        let stop_bits = match overrides.stop_bits.unwrap_or(serial_opts.stop_bits) {
+            1 => serialport::StopBits::One,
+            2 => serialport::StopBits::Two,
+            bits => {
+                return Err(McpError::invalid_params(
+                    format!("Invalid number of stop bits: {}", bits),
+                    None,
+                ));
+            }
+        };
+
+        let parity = match overrides.parity.unwrap_or(serial_opts.parity) {
+            Parity::None => serialport::Parity::None,
+            Parity::Odd => serialport::Parity::Odd,
+            Parity::Even => serialport::Parity::Even,
+        };
+
+        let flow_control = match overrides.flow_control.unwrap_or(serial_opts.flow_control) {
+            FlowControl::None => serialport::FlowControl::None,
+            FlowControl::Software => serialport::FlowControl::Software,
+            FlowControl::Hardware => serialport::FlowControl::Hardware,
+        };
+
+        Ok(SerialLine {
+            baudrate,
+            data_bits,
+            parity,
+            stop_bits,
+            flow_control,
+        })
+    }
+
+    /// Decoder of the text received from the port, as configured in the
+    /// manifest with the per-call overrides applied
+    pub fn serial_decoder(&self, overrides: &SerialTextRequest) -> Result<TextDecoder, McpError> {
+        let serial_opts = SerialOptions::from(self)?;
+
+        TextDecoder::new(
+            overrides
+                .encoding
+                .as_deref()
+                .unwrap_or(&serial_opts.encoding),
+            overrides.strip_ansi.unwrap_or(serial_opts.strip_ansi),
+        )
+        .map_err(|e| McpError::invalid_params(e, None))
+    }
+
+    /// Symbolizer of the firmware ELF given in the request, or configured in
+    /// the manifest, if any
+    pub fn serial_symbolizer(&self, elf: Option<String>) -> Result<Option<Symbolizer>, McpError> {
+        let serial_opts = SerialOptions::from(self)?;
+
+        let Some(elf) = elf.map(PathBuf::from).or_else(|| serial_opts.elf.clone()) else {
+            return Ok(None);
+        };
+
+        Symbolizer::open(&self.cwd.join(elf))
+            .map(Some)
+            .map_err(|e| McpError::invalid_params(e, None))
+    }
+
+    /// Decoder of the dictionary based logs, with the log dictionary given in
+    /// the request, or configured in the manifest, if any
+    pub fn serial_dictionary(
+        &self,
+        log_dictionary: Option<String>,
+    ) -> Result<Option<DictionaryDecoder>, McpError> {
+        let serial_opts = SerialOptions::from(self)?;
+
+        let Some(path) = log_dictionary
+            .map(PathBuf::from)
+            .or_else(|| serial_opts.log_dictionary.clone())
+        else {
+            return Ok(None);
+        };
+
+        LogDictionary::open(&self.cwd.join(path))
+            .map(|dictionary| Some(DictionaryDecoder::new(dictionary)))
+            .map_err(|e| McpError::invalid_params(e, None))
+    }
+
+    /// Recorder writing the data received from the port to files under the
+    /// record directory configured in the manifest
+    pub fn serial_recorder(&self, port: &str) -> Result<SerialRecorder, McpError> {
+        let serial_opts = SerialOptions::from(self)?;
+
+        SerialRecorder::create(
+            &self.cwd.join(&serial_opts.record.dir),
+            port,
+            &serial_opts.record,
+        )
+        .map_err(|e| {
+            McpError::internal_error(format!("Failed to create record files: {}", e), None)
+        })
+    }
+}
+
+/// Describe the files written by a recorder, for the tool results
+pub fn recorded_files(recorder: &SerialRecorder) -> String {
+    let files = recorder.files().lock().unwrap().clone();
+    record_summary(&files, recorder.error().map(str::to_string))
+}
+
+pub fn record_summary(files: &[PathBuf], error: Option<String>) -> String {
+    let mut response = String::from("\n\nRecorded to:\n");
+    for file in files {
+        response.push_str(&format!("- {}\n", file.display()));
+    }
+    if let Some(error) = error {
+        response.push_str(&format!("\nRecording stopped with error: {}\n", error));
+    }
+    response
+}
+
+impl SerialOptions {
+    pub fn from(spec: &Commands) -> Result<&Self, McpError> {
+        spec.manifest
+            .serial
+            .as_ref()
+            .ok_or(McpError::invalid_params(
+                "Serial options are not defined in the manifest".to_string(),
+                None,
+            ))
+    }
+}
+
+impl LineEnding {
+    pub fn as_bytes(&self) -> &'static [u8] {
+        match self {
+            LineEnding::None => b"",
+            LineEnding::Lf => b"\n",
+            LineEnding::Cr => b"\r",
+            LineEnding::CrLf => b"\r\n",
+        }
+    }
+}
+
+/// Build the bytes to write from the text or hex encoded data.
+pub fn serial_payload(
+    data: &str,
+    hex: Option<bool>,
+    line_ending: Option<LineEnding>,
+    opts: &SerialOptions,
+) -> Result<Vec<u8>, McpError> {
+    if hex.unwrap_or(false) {
+        return parse_hex(data)
+            .map_err(|e| McpError::invalid_params(format!("Invalid hex data: {}", e), None));
+    }
+
+    let mut bytes = data.as_bytes().to_vec();
+    bytes.extend_from_slice(line_ending.unwrap_or(opts.line_ending).as_bytes());
+    Ok(bytes)
+}
+
+/// Parse hex encoded bytes, separated by whitespaces or not, with optional
+/// `0x` prefixes, e.g. `01 02 ff`, `0102ff` or `0x01 0x02 0xff`.
+fn parse_hex(data: &str) -> Result<Vec<u8>, String> {
+    let digits = data
+        .split_whitespace()
+        .map(|word| word.trim_start_matches("0x").trim_start_matches("0X"))
+        .collect::<String>();
+
+    if !digits.is_ascii() {
+        return Err("non-ASCII characters".to_string());
+    }
+
+    if digits.len() % 2 != 0 {
+        return Err("odd number of hex digits".to_string());
+    }
+
+    (0..digits.len())
+        .step_by(2)
+        .map(|i| {
+            u8::from_str_radix(&digits[i..i + 2], 16)
+                .map_err(|_| format!("invalid hex byte '{}'", &digits[i..i + 2]))
+        })
+        .collect()
+}
+
+pub fn serial_write(port: &mut Box<dyn SerialPort>, data: &[u8]) -> io::Result<()> {
+    port.write_all(data)?;
+    port.flush()
+}
+
+/// Open a local serial port, or a network port if the path is a
+/// `tcp://` or `rfc2217://` URL.
+pub fn serial_open(path: &str, line: &SerialLine) -> serialport::Result<Box<dyn SerialPort>> {
+    if is_network_port(path) {
+        return Ok(Box::new(NetworkPort::open(path, line)?));
+    }
+
+    serialport::new(path, line.baudrate)
+        .data_bits(line.data_bits)
+        .parity(line.parity)
+        .stop_bits(line.stop_bits)
+        .flow_control(line.flow_control)
         .dtr_on_open(false)
         .open()
 }
 
-fn serial_reset(port: &mut Box<dyn SerialPort>, opts: &SerialOptions) -> serialport::Result<()> {
-    let interval = Duration::from_millis(opts.reset_interval);
+/// Reset the device with the configured reset method, or with the named
+/// sequence if specified.
+pub fn serial_reset(
+    port: &mut Box<dyn SerialPort>,
+    opts: &SerialOptions,
+    sequence: Option<&str>,
+) -> serialport::Result<()> {
+    let method = match sequence {
+        Some(name) => Some(SerialResetMethod::Sequence(name.to_string())),
+        None => opts.reset.clone(),
+    };
+
+    let Some(method) = method else {
+        return Ok(()); // no-op
+    };
 
-    match opts.reset {
-        Some(SerialResetMethod::DTR) => {
-            port.write_data_terminal_ready(true)?;
-            sleep(interval);
-            port.write_data_terminal_ready(false)?;
-            sleep(interval);
+    serial_reset_with(port, &method, opts)
+}
+
+/// Reset the device with the reset method
+pub fn serial_reset_with(
+    port: &mut Box<dyn SerialPort>,
+    method: &SerialResetMethod,
+    opts: &SerialOptions,
+) -> serialport::Result<()> {
+    for step in reset_steps(method, opts)? {
+        if let Some(level) = step.dtr {
+            port.write_data_terminal_ready(level)?;
+        }
+        if let Some(level) = step.rts {
+            port.write_request_to_send(level)?;
         }
-        Some(SerialResetMethod::RTS) => {
-            port.write_request_to_send(true)?;
-            sleep(interval);
-            port.write_request_to_send(false)?;
-            sleep(interval);
+        if let Some(delay) = step.delay {
+            sleep(Duration::from_millis(delay));
         }
-        None => (), // no-op
     }
 
     Ok(())
 }
+
+/// Steps of a reset method, with the single line pulses expressed as
+/// sequences as well.
+fn reset_steps(
+    method: &SerialResetMethod,
+    opts: &SerialOptions,
+) -> serialport::Result<Vec<ResetStep>> {
+    let pulse = |dtr: bool, level: bool| ResetStep {
+        dtr: dtr.then_some(level),
+        rts: (!dtr).then_some(level),
+        delay: Some(opts.reset_interval),
+    };
+
+    match method {
+        SerialResetMethod::DTR => Ok(vec![pulse(true, true), pulse(true, false)]),
+        SerialResetMethod::RTS => Ok(vec![pulse(false, true), pulse(false, false)]),
+        SerialResetMethod::Sequence(name) => {
+            opts.reset_sequences.get(name).cloned().ok_or_else(|| {
+                serialport::Error::new(
+                    serialport::ErrorKind::InvalidInput,
+                    format!("Reset sequence '{}' is not defined", name),
+                )
+            })
+        }
+    }
+}
@@ -0,0 +1,314 @@
+use regex::Regex;
+use rmcp::{
+    ErrorData as McpError,
+    handler::server::wrapper::Parameters,
+    model::{CallToolResult, Content},
+    schemars, tool, tool_router,
+};
+use serde::Deserialize;
+
+use crate::{
+    commands::Commands,
+    commands_serial::{
+        SerialLineRequest, SerialTextRequest, record_summary, recorded_files, serial_open,
+        serial_reset,
+    },
+    manifest::SerialOptions,
+    serial_crash::analyze_crashes,
+    serial_monitor::SerialMonitor,
+};
+
+#[derive(Debug, Deserialize, schemars::JsonSchema)]
+pub struct SerialMonitorStartRequest {
+    #[schemars(description = "The port to monitor, or its alias")]
+    pub port: String,
+
+    #[schemars(
+        description = "Whether to reset the device after opening the port, defaults to false"
+    )]
+    pub reset: Option<bool>,
+
+    #[schemars(
+        description = "Whether to record the raw data and the timestamped lines received to \
+            files under the project, defaults to false"
+    )]
+    pub record: Option<bool>,
+
+    #[serde(flatten)]
+    pub text: SerialTextRequest,
+
+    #[serde(flatten)]
+    pub line: SerialLineRequest,
+}
+
+#[derive(Debug, Deserialize, schemars::JsonSchema)]
+pub struct SerialMonitorReadRequest {
+    #[schemars(description = "The monitored port, or its alias")]
+    pub port: String,
+
+    #[schemars(
+        description = "Cursor returned by the previous read, to read only lines received \
+            after it. Defaults to 0, which reads from the oldest line in the history."
+    )]
+    pub cursor: Option<u64>,
+
+    #[schemars(description = "Maximum number of lines to return, defaults to 200")]
+    pub max_lines: Option<usize>,
+
+    #[schemars(
+        description = "Path to the firmware ELF to resolve the addresses of crash dumps \
+            against, defaults to the one configured in the manifest"
+    )]
+    pub elf: Option<String>,
+}
+
+#[derive(Debug, Deserialize, schemars::JsonSchema)]
+pub struct SerialMonitorSearchRequest {
+    #[schemars(description = "The monitored port, or its alias")]
+    pub port: String,
+
+    #[schemars(description = "Regular expression to search for in the history")]
+    pub pattern: String,
+
+    #[schemars(description = "Maximum number of most recent matches to return, defaults to 50")]
+    pub max_results: Option<usize>,
+}
+
+#[derive(Debug, Deserialize, schemars::JsonSchema)]
+pub struct SerialMonitorStopRequest {
+    #[schemars(description = "The monitored port, or its alias")]
+    pub port: String,
+}
+
+#[tool_router(router = serial_monitor_router, vis = "pub")]
+impl Commands {
+    #[tool(
+        name = "serial_monitor_start",
+        description = "Start monitoring the specified serial port in background. Every \
+            line received is timestamped and kept in a history, which can be read \
+            with `serial_monitor_read` and searched with `serial_monitor_search`, \
+            until the monitor is stopped with `serial_monitor_stop`."
+    )]
+    async fn serial_monitor_start(
+        &self,
+        Parameters(SerialMonitorStartRequest {
+            port,
+            reset,
+            record,
+            text,
+            line,
+        }): Parameters<SerialMonitorStartRequest>,
+    ) -> Result<CallToolResult, McpError> {
+        let port = self.resolve_port(&port)?;
+
+        let serial_opts = SerialOptions::from(self)?;
+
+        if self.monitors.contains(&port) {
+            return Err(McpError::invalid_params(
+                format!("Port {} is already being monitored", port),
+                None,
+            ));
+        }
+
+        let decoder = self.serial_decoder(&text)?;
+        let lease = self.leases.acquire(&port, "serial_monitor")?;
+        let line = self.serial_line(&port, &line)?;
+        let mut device = serial_open(&port, &line)
+            .map_err(|e| McpError::internal_error(format!("Failed to open device: {}", e), None))?;
+
+        self.logger.info(
+            "serial",
+            format!("Opened {} at {} for monitoring", port, line),
+        );
+
+        if reset.unwrap_or(false) {
+            serial_reset(&mut device, serial_opts, None).map_err(|e| {
+                McpError::internal_error(format!("Failed to reset device: {}", e), None)
+            })?;
+        }
+
+        let recorder = match record {
+            Some(true) => Some(self.serial_recorder(&port)?),
+            _ => None,
+        };
+        let mut response = format!(
+            "Started monitoring {}, keeping up to {} lines of history.",
+            port, serial_opts.monitor_history
+        );
+        if let Some(recorder) = &recorder {
+            response.push_str(&recorded_files(recorder));
+        }
+
+        let monitor = SerialMonitor::start(
+            &port,
+            line,
+            device,
+            lease,
+            serial_opts.monitor_history,
+            decoder,
+            recorder,
+        );
+        self.monitors.insert(port.clone(), monitor);
+
+        Ok(CallToolResult::success(vec![Content::text(response)]))
+    }
+
+    #[tool(
+        name = "serial_monitor_read",
+        description = "Read lines received by the monitor of the specified serial \
+            port. Pass the cursor returned by the previous read to get only new lines.",
+        annotations(read_only_hint = true)
+    )]
+    async fn serial_monitor_read(
+        &self,
+        Parameters(SerialMonitorReadRequest {
+            port,
+            cursor,
+            max_lines,
+            elf,
+        }): Parameters<SerialMonitorReadRequest>,
+    ) -> Result<CallToolResult, McpError> {
+        let port = self.resolve_port(&port)?;
+
+        let symbolizer = self.serial_symbolizer(elf)?;
+
+        let ((lines, next, dropped), (_, error), record) = self
+            .monitors
+            .with(&port, |monitor| {
+                (
+                    monitor.read_since(cursor.unwrap_or(0), max_lines.unwrap_or(200)),
+                    monitor.status(),
+                    monitor.record_status(),
+                )
+            })
+            .ok_or_else(|| not_monitored(&port))?;
+
+        let mut response = String::new();
+        if dropped > 0 {
+            response.push_str(&format!(
+                "{} lines after the cursor were dropped from the history.\n\n",
+                dropped
+            ));
+        }
+
+        if lines.is_empty() {
+            response.push_str("No new lines received.\n");
+        } else {
+            response.push_str("Lines received, formatted as `[time] message`:\n\n");
+            response.push_str("```\n");
+            for line in &lines {
+                response.push_str(&line.to_log());
+                response.push('\n');
+            }
+            response.push_str("```\n");
+        }
+
+        if let Some(error) = error {
+            response.push_str(&format!("\nThe monitor stopped with error: {}\n", error));
+        }
+
+        if let Some(symbolizer) = &symbolizer {
+            let lines = lines.iter().map(|line| &line.text).collect::<Vec<_>>();
+            if let Some(analysis) = analyze_crashes(symbolizer, &lines) {
+                response.push('\n');
+                response.push_str(&analysis);
+            }
+        }
+
+        response.push_str(&format!("\nNext cursor: {}", next));
+
+        if let Some((files, error)) = record {
+            response.push_str(&record_summary(&files, error));
+        }
+
+        Ok(CallToolResult::success(vec![Content::text(response)]))
+    }
+
+    #[tool(
+        name = "serial_monitor_search",
+        description = "Search the history of the monitor of the specified serial port \
+            for lines matching a regular expression.",
+        annotations(read_only_hint = true)
+    )]
+    async fn serial_monitor_search(
+        &self,
+        Parameters(SerialMonitorSearchRequest {
+            port,
+            pattern,
+            max_results,
+        }): Parameters<SerialMonitorSearchRequest>,
+    ) -> Result<CallToolResult, McpError> {
+        let port = self.resolve_port(&port)?;
+
+        let pattern = Regex::new(&pattern).map_err(|e| {
+            McpError::invalid_params(format!("Invalid regular expression: {}", e), None)
+        })?;
+
+        let lines = self
+            .monitors
+            .with(&port, |monitor| {
+                monitor.search(&pattern, max_results.unwrap_or(50))
+            })
+            .ok_or_else(|| not_monitored(&port))?;
+
+        if lines.is_empty() {
+            return Ok(CallToolResult::success(vec![Content::text(format!(
+                "No lines matching `{}` found.",
+                pattern.as_str()
+            ))]));
+        }
+
+        let mut response = String::new();
+        response.push_str("Matching lines, formatted as `#cursor [time] message`:\n\n");
+        response.push_str("```\n");
+        for line in &lines {
+            response.push_str(&format!("#{} {}\n", line.seq, line.to_log()));
+        }
+        response.push_str("```");
+
+        Ok(CallToolResult::success(vec![Content::text(response)]))
+    }
+
+    #[tool(
+        name = "serial_monitor_stop",
+        description = "Stop monitoring the specified serial port and close it. The \
+            history is discarded."
+    )]
+    async fn serial_monitor_stop(
+        &self,
+        Parameters(SerialMonitorStopRequest { port }): Parameters<SerialMonitorStopRequest>,
+    ) -> Result<CallToolResult, McpError> {
+        let port = self.resolve_port(&port)?;
+
+        let mut monitor = self
+            .monitors
+            .remove(&port)
+            .ok_or_else(|| not_monitored(&port))?;
+
+        let started = monitor.started;
+        let (received, error) = monitor.stop();
+        let record = monitor.record_status();
+
+        self.logger
+            .info("serial", format!("Stopped monitoring {}", port));
+
+        let mut response = format!(
+            "Stopped monitoring {}, {} lines received since {}.",
+            port,
+            received,
+            started.format("%H:%M:%S")
+        );
+        if let Some(error) = error {
+            response.push_str(&format!("\n\nThe monitor stopped with error: {}", error));
+        }
+        if let Some((files, error)) = record {
+            response.push_str(&record_summary(&files, error));
+        }
+
+        Ok(CallToolResult::success(vec![Content::text(response)]))
+    }
+}
+
+fn not_monitored(port: &str) -> McpError {
+    McpError::invalid_params(format!("Port {} is not being monitored", port), None)
+}
@@ -0,0 +1,513 @@
+use std::{
+    io,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use handlebars::{Handlebars, no_escape};
+use regex::Regex;
+use rmcp::{
+    ErrorData as McpError,
+    handler::server::tool::{ToolCallContext, ToolRoute},
+    model::*,
+};
+use serde_json::{Map, Value as JsonValue};
+use serialport::SerialPort;
+
+use crate::{
+    commands::Commands,
+    commands_serial::{
+        SerialLineRequest, SerialTextRequest, serial_open, serial_payload, serial_reset,
+        serial_write,
+    },
+    manifest::{ScenarioReset, ScenarioStep, SerialOptions, SerialScenario},
+    serial_format::LineSplitter,
+};
+
+/// What a scenario step does
+enum Action<'a> {
+    Reset(Option<&'a str>),
+    Send(&'a str),
+    Expect {
+        pattern: Regex,
+        timeout: Duration,
+        capture: Option<&'a str>,
+    },
+    Delay(Duration),
+    Assert {
+        text: &'a str,
+        matches: Regex,
+    },
+}
+
+impl ScenarioStep {
+    fn action(&self) -> Result<Action<'_>, String> {
+        let regex = |pattern: &str| {
+            Regex::new(pattern).map_err(|e| format!("Invalid regular expression: {}", e))
+        };
+
+        let action = match self {
+            ScenarioStep {
+                reset: Some(reset),
+                send: None,
+                expect: None,
+                delay: None,
+                assert: None,
+                ..
+            } => match reset {
+                ScenarioReset::Enabled(true) => Action::Reset(None),
+                ScenarioReset::Enabled(false) => return Err("`reset` is false".to_string()),
+                ScenarioReset::Sequence(sequence) => Action::Reset(Some(sequence)),
+            },
+            ScenarioStep {
+                reset: None,
+                send: Some(text),
+                expect: None,
+                delay: None,
+                assert: None,
+                ..
+            } => Action::Send(text),
+            ScenarioStep {
+                reset: None,
+                send: None,
+                expect: Some(pattern),
+                delay: None,
+                assert: None,
+                ..
+            } => Action::Expect {
+                pattern: regex(pattern)?,
+                timeout: Duration::from_millis(self.timeout.unwrap_or(5000)),
+                capture: self.capture.as_deref(),
+            },
+            ScenarioStep {
+                reset: None,
+                send: None,
+                expect: None,
+                delay: Some(delay),
+                assert: None,
+                ..
+            } => Action::Delay(Duration::from_millis(*delay)),
+            ScenarioStep {
+                reset: None,
+                send: None,
+                expect: None,
+                delay: None,
+                assert: Some(text),
+                ..
+            } => Action::Assert {
+                text,
+                matches: regex(
+                    self.matches
+                        .as_deref()
+                        .ok_or("`assert` requires `matches`")?,
+                )?,
+            },
+            _ => {
+                return Err(
+                    "exactly one of `reset`, `send`, `expect`, `delay` and `assert` must be set"
+                        .to_string(),
+                );
+            }
+        };
+
+        if self.timeout.is_some() && !matches!(action, Action::Expect { .. }) {
+            return Err("`timeout` only applies to `expect`".to_string());
+        }
+        if self.capture.is_some() && !matches!(action, Action::Expect { .. }) {
+            return Err("`capture` only applies to `expect`".to_string());
+        }
+        if self.matches.is_some() && !matches!(action, Action::Assert { .. }) {
+            return Err("`matches` only applies to `assert`".to_string());
+        }
+
+        Ok(action)
+    }
+
+    /// Check the step sets a single action with valid regular expressions,
+    /// as when the scenario runs
+    pub fn validate(&self) -> Result<(), String> {
+        self.action().map(|_| ())
+    }
+}
+
+impl Action<'_> {
+    fn describe(&self) -> String {
+        match self {
+            Action::Reset(None) => "reset".to_string(),
+            Action::Reset(Some(sequence)) => format!("reset with sequence '{}'", sequence),
+            Action::Send(text) => format!("send `{}`", text),
+            Action::Expect { pattern, .. } => format!("expect `{}`", pattern.as_str()),
+            Action::Delay(delay) => format!("delay {}ms", delay.as_millis()),
+            Action::Assert { text, matches } => {
+                format!("assert `{}` matches `{}`", text, matches.as_str())
+            }
+        }
+    }
+}
+
+/// State of a running scenario
+struct ScenarioRun<'a> {
+    device: Box<dyn SerialPort>,
+    serial_opts: &'a SerialOptions,
+    handlebars: Handlebars<'static>,
+
+    /// Splits the bytes received into lines, decoded once complete
+    splitter: LineSplitter,
+
+    /// Lines received since the scenario started, each ending with a newline
+    output: String,
+
+    /// Offset after the last match of `expect`, in the output followed by
+    /// the incomplete line
+    cursor: usize,
+
+    /// Arguments of the tool and variables captured
+    variables: Map<String, JsonValue>,
+}
+
+impl ScenarioRun<'_> {
+    /// Length of the output, including the incomplete line
+    fn output_len(&self) -> usize {
+        self.output.len() + self.splitter.partial().len()
+    }
+
+    /// The output from the offset, including the incomplete line. Returns the
+    /// offset actually used, moved to a character boundary in case the
+    /// incomplete line it pointed into decoded differently once completed.
+    fn output_from(&self, offset: usize) -> (usize, String) {
+        let partial = self.splitter.partial();
+        if offset < self.output.len() {
+            let (start, output) = tail(&self.output, offset);
+            (start, format!("{}{}", output, partial))
+        } else {
+            let (start, output) = tail(&partial, offset - self.output.len());
+            (self.output.len() + start, output.to_string())
+        }
+    }
+
+    fn receive(&mut self) -> Result<(), String> {
+        let mut buffer = vec![0; 1024];
+        match self.device.read(&mut buffer) {
+            Ok(bytes) => {
+                for line in self.splitter.feed(&buffer[..bytes]) {
+                    self.output.push_str(&line);
+                    self.output.push('\n');
+                }
+                Ok(())
+            }
+            Err(e) if e.kind() == io::ErrorKind::TimedOut => Ok(()),
+            Err(e) => Err(format!("Failed to read from device: {}", e)),
+        }
+    }
+
+    fn render(&self, template: &str) -> Result<String, String> {
+        self.handlebars
+            .render_template(template, &self.variables)
+            .map_err(|e| format!("Template rendering error: {}", e))
+    }
+
+    /// Run the action, returning what happened if it passed, or why it
+    /// failed otherwise.
+    fn run(&mut self, action: &Action) -> Result<String, String> {
+        match action {
+            Action::Reset(sequence) => {
+                serial_reset(&mut self.device, self.serial_opts, *sequence)
+                    .map_err(|e| format!("Failed to reset device: {}", e))?;
+                Ok("Device reset.".to_string())
+            }
+            Action::Send(template) => {
+                let text = self.render(template)?;
+                let bytes = serial_payload(&text, None, None, self.serial_opts)
+                    .map_err(|e| e.message.to_string())?;
+                serial_write(&mut self.device, &bytes)
+                    .map_err(|e| format!("Failed to write to device: {}", e))?;
+                Ok(format!("Sent `{}`.", text))
+            }
+            Action::Expect {
+                pattern,
+                timeout,
+                capture,
+            } => {
+                let start_time = Instant::now();
+                loop {
+                    let (cursor, searched) = self.output_from(self.cursor);
+                    self.cursor = cursor;
+
+                    if let Some(captures) = pattern.captures(&searched) {
+                        let matched = captures.get(0).unwrap();
+                        for name in pattern.capture_names().flatten() {
+                            if let Some(value) = captures.name(name) {
+                                self.variables
+                                    .insert(name.to_string(), value.as_str().trim_end().into());
+                            }
+                        }
+                        if let Some(capture) = capture {
+                            let value = captures.get(1).unwrap_or(matched);
+                            self.variables
+                                .insert(capture.to_string(), value.as_str().trim_end().into());
+                        }
+
+                        self.cursor += matched.end();
+                        return Ok(format!(
+                            "Matched `{}` after {:.3}s.",
+                            matched.as_str().trim(),
+                            start_time.elapsed().as_secs_f64()
+                        ));
+                    }
+
+                    if start_time.elapsed() >= *timeout {
+                        return Err(format!("No match within {}ms.", timeout.as_millis()));
+                    }
+                    self.receive()?;
+                }
+            }
+            Action::Delay(delay) => {
+                let start_time = Instant::now();
+                while start_time.elapsed() < *delay {
+                    self.receive()?;
+                }
+                Ok(format!("Waited {}ms.", delay.as_millis()))
+            }
+            Action::Assert { text, matches } => {
+                let text = self.render(text)?;
+                if matches.is_match(&text) {
+                    Ok(format!("`{}` matches.", text))
+                } else {
+                    Err(format!("`{}` doesn't match `{}`.", text, matches.as_str()))
+                }
+            }
+        }
+    }
+}
+
+impl Commands {
+    async fn run_scenario(
+        &self,
+        name: &str,
+        scenario: &SerialScenario,
+        args: &JsonValue,
+    ) -> Result<CallToolResult, McpError> {
+        let port = args
+            .get("port")
+            .and_then(JsonValue::as_str)
+            .map(str::to_string)
+            .or_else(|| scenario.port.clone())
+            .ok_or_else(|| McpError::invalid_params("Missing argument 'port'", None))?;
+        let port = self.resolve_port(&port)?;
+
+        let serial_opts = SerialOptions::from(self)?;
+
+        let actions = scenario
+            .steps
+            .iter()
+            .enumerate()
+            .map(|(i, step)| {
+                step.action().map_err(|e| {
+                    McpError::invalid_params(
+                        format!("Step {} of scenario '{}' is invalid: {}", i + 1, name, e),
+                        None,
+                    )
+                })
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let splitter = LineSplitter::new(self.serial_decoder(&SerialTextRequest::default())?);
+
+        let _lease = self.leases.acquire(&port, name)?;
+        let line = self.serial_line(&port, &SerialLineRequest::default())?;
+        let mut device = serial_open(&port, &line)
+            .map_err(|e| McpError::internal_error(format!("Failed to open device: {}", e), None))?;
+
+        device
+            .set_timeout(Duration::from_millis(100))
+            .map_err(|e| McpError::internal_error(format!("Failed to set timeout: {}", e), None))?;
+
+        self.logger.info(
+            "serial",
+            format!("Running scenario '{}' on {} at {}", name, port, line),
+        );
+
+        let mut handlebars = Handlebars::new();
+        handlebars.register_escape_fn(no_escape);
+
+        let mut run = ScenarioRun {
+            device,
+            serial_opts,
+            handlebars,
+            splitter,
+            output: String::new(),
+            cursor: 0,
+            variables: args.as_object().cloned().unwrap_or_default(),
+        };
+        run.variables
+            .insert("port".to_string(), port.clone().into());
+
+        let start_time = Instant::now();
+        let mut steps = String::new();
+        let mut failed = None;
+
+        for (i, action) in actions.iter().enumerate() {
+            let step_time = Instant::now();
+            let output_start = run.output_len();
+
+            let result = run.run(action);
+
+            let (_, transcript) = run.output_from(output_start);
+            let transcript = transcript.trim_end();
+
+            steps.push_str(&format!(
+                "### {}. {} — {} in {:.3}s\n\n{}\n\n",
+                i + 1,
+                action.describe(),
+                if result.is_ok() { "passed" } else { "failed" },
+                step_time.elapsed().as_secs_f64(),
+                match &result {
+                    Ok(detail) | Err(detail) => detail,
+                }
+            ));
+            if !transcript.is_empty() {
+                steps.push_str(&format!("```\n{}\n```\n\n", transcript));
+            }
+
+            if result.is_err() {
+                failed = Some(i);
+                break;
+            }
+        }
+
+        let elapsed = start_time.elapsed().as_secs_f64();
+        let mut response = match failed {
+            None => format!(
+                "Scenario '{}' passed, {} steps in {:.3}s.\n\n",
+                name,
+                actions.len(),
+                elapsed
+            ),
+            Some(i) => format!(
+                "Scenario '{}' failed at step {} of {}, after {:.3}s.\n\n",
+                name,
+                i + 1,
+                actions.len(),
+                elapsed
+            ),
+        };
+
+        response.push_str("## Steps\n\n");
+        response.push_str(&steps);
+
+        let captured = run
+            .variables
+            .iter()
+            .filter(|(name, _)| args.get(name.as_str()).is_none() && *name != "port")
+            .collect::<Vec<_>>();
+        if !captured.is_empty() {
+            response.push_str("## Variables\n\n");
+            for (name, value) in captured {
+                response.push_str(&format!(
+                    "- {}: `{}`\n",
+                    name,
+                    value.as_str().unwrap_or_default()
+                ));
+            }
+        }
+
+        self.logger.info(
+            "serial",
+            format!(
+                "Scenario '{}' {}",
+                name,
+                if failed.is_none() { "passed" } else { "failed" }
+            ),
+        );
+
+        let content = vec![Content::text(response.trim_end())];
+        if failed.is_none() {
+            Ok(CallToolResult::success(content))
+        } else {
+            Ok(CallToolResult::error(content))
+        }
+    }
+}
+
+impl SerialScenario {
+    pub fn to_tool_route(&self, name: &String) -> ToolRoute<Commands> {
+        let scenario = name.clone();
+
+        ToolRoute::<Commands> {
+            attr: Tool {
+                name: name.to_string().into(),
+                title: None,
+                description: Some(self.description.as_str().to_string().into()),
+                input_schema: Arc::new(self.to_schema()),
+                output_schema: None,
+                annotations: None,
+                icons: None,
+            },
+            call: Arc::new(move |tcc: ToolCallContext<'_, Commands>| {
+                // Look up by the scenario name instead of the requested tool
+                // name, which may have been namespaced
+                let name = scenario.clone();
+
+                Box::pin(async move {
+                    let name = name.as_str();
+
+                    let scenario =
+                        tcc.service
+                            .manifest
+                            .serial_scenarios
+                            .get(name)
+                            .ok_or_else(|| {
+                                McpError::invalid_params(
+                                    format!("Scenario '{}' not found", name),
+                                    None,
+                                )
+                            })?;
+
+                    let args = match tcc.arguments {
+                        Some(args) => JsonValue::Object(args),
+                        None => JsonValue::Object(Map::new()),
+                    };
+
+                    tcc.service.run_scenario(name, scenario, &args).await
+                })
+            }),
+        }
+    }
+
+    fn to_schema(&self) -> Map<String, JsonValue> {
+        let mut port = Map::new();
+        port.insert("type".to_string(), "string".into());
+        port.insert(
+            "description".to_string(),
+            "The port to run the scenario on, or its alias".into(),
+        );
+        if let Some(default) = &self.port {
+            port.insert("default".to_string(), default.as_str().into());
+        }
+
+        let mut schema = Map::new();
+        schema.insert("type".to_string(), "object".into());
+        schema.insert(
+            "properties".to_string(),
+            JsonValue::Object(Map::from_iter([("port".to_string(), port.into())])),
+        );
+        schema.insert(
+            "required".to_string(),
+            match self.port {
+                Some(_) => JsonValue::Array(vec![]),
+                None => JsonValue::Array(vec!["port".into()]),
+            },
+        );
+
+        schema
+    }
+}
+
+/// The text from the first character boundary at or after the offset, and
+/// that boundary. Empty if the offset is beyond the text.
+fn tail(text: &str, offset: usize) -> (usize, &str) {
+    let start = (offset.min(text.len())..=text.len())
+        .find(|index| text.is_char_boundary(*index))
+        .unwrap_or(text.len());
+    (start, &text[start..])
+}
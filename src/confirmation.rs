@@ -0,0 +1,57 @@
+use rmcp::{
+    ErrorData as McpError, Peer, RoleServer, elicit_safe, schemars, service::ElicitationError,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{commands::Commands, manifest::ConfirmFallback};
+
+#[derive(Debug, Serialize, Deserialize, schemars::JsonSchema)]
+pub struct Confirmation {
+    #[schemars(description = "Whether to proceed with the operation")]
+    pub confirmed: bool,
+}
+
+elicit_safe!(Confirmation);
+
+impl Commands {
+    /// Ask the user to confirm a destructive operation via MCP elicitation.
+    ///
+    /// If the client doesn't support elicitation, the `confirm_fallback` policy
+    /// of the manifest decides whether the operation may proceed.
+    pub async fn confirm(
+        &self,
+        peer: &Peer<RoleServer>,
+        operation: &str,
+        detail: &str,
+    ) -> Result<(), McpError> {
+        let message = format!(
+            "The agent is about to run '{}':\n\n{}\n\nDo you want to proceed?",
+            operation,
+            detail.trim()
+        );
+
+        match peer.elicit::<Confirmation>(message).await {
+            Ok(Some(Confirmation { confirmed: true })) => Ok(()),
+            Ok(_) | Err(ElicitationError::UserDeclined) | Err(ElicitationError::UserCancelled) => {
+                Err(McpError::invalid_request(
+                    format!("The user declined to run '{}'", operation),
+                    None,
+                ))
+            }
+            Err(ElicitationError::CapabilityNotSupported) => match self.manifest.confirm_fallback {
+                ConfirmFallback::Allow => Ok(()),
+                ConfirmFallback::Deny => Err(McpError::invalid_request(
+                    format!(
+                        "'{}' requires confirmation, but the client doesn't support elicitation",
+                        operation
+                    ),
+                    None,
+                )),
+            },
+            Err(e) => Err(McpError::internal_error(
+                format!("Failed to request confirmation: {}", e),
+                None,
+            )),
+        }
+    }
+}
@@ -9,4 +9,7 @@ pub enum ManifestError {
 
     #[error("Failed to parse manifest file {0}: {1}")]
     Parse(PathBuf, serde_yml::Error),
+
+    #[error("Invalid manifest file {0}: {1}")]
+    Invalid(PathBuf, String),
 }
@@ -0,0 +1,353 @@
+//! Formats of images to flash. Raw binaries are written at the address given,
+//! while Intel HEX, ELF and UF2 images carry the addresses of their contents.
+
+use std::{fmt, path::Path};
+
+use object::{
+    Endianness, FileKind,
+    elf::{FileHeader32, FileHeader64, PT_LOAD},
+    read::elf::{FileHeader, ProgramHeader},
+};
+
+pub use crate::manifest::ImageFormat;
+
+/// Magic numbers of UF2 blocks
+const UF2_MAGIC_START0: u32 = 0x0A32_4655;
+const UF2_MAGIC_START1: u32 = 0x9E5D_5157;
+const UF2_MAGIC_END: u32 = 0x0AB1_6F30;
+const UF2_BLOCK_SIZE: usize = 512;
+const UF2_PAYLOAD_SIZE: usize = 476;
+
+/// UF2 flag of blocks not meant for the main flash, e.g. comments
+const UF2_FLAG_NOT_MAIN_FLASH: u32 = 0x0000_0001;
+
+impl ImageFormat {
+    /// Detect the format from the extension and the content of the image,
+    /// falling back to a raw binary. A `.bin` file is always a raw binary,
+    /// and a `.hex`, `.ihex` or `.uf2` one is always Intel HEX or UF2, whose
+    /// parsing then reports any invalid content. Other files are sniffed:
+    /// ELF and UF2 from their magic numbers, and Intel HEX from a first
+    /// record (after blank and `;` comment lines) starting with `:`.
+    pub fn detect(path: &Path, data: &[u8]) -> Self {
+        let extension = path
+            .extension()
+            .map(|extension| extension.to_string_lossy().to_lowercase());
+
+        match extension.as_deref() {
+            Some("bin") => ImageFormat::Binary,
+            Some("hex" | "ihex") => ImageFormat::IntelHex,
+            Some("uf2") => ImageFormat::Uf2,
+            _ if data.starts_with(b"\x7fELF") => ImageFormat::Elf,
+            _ if data.len() >= UF2_BLOCK_SIZE
+                && read_u32(data, 0) == UF2_MAGIC_START0
+                && read_u32(data, 4) == UF2_MAGIC_START1 =>
+            {
+                ImageFormat::Uf2
+            }
+            _ if first_record(data).starts_with(b":") => ImageFormat::IntelHex,
+            _ => ImageFormat::Binary,
+        }
+    }
+}
+
+/// The data from the first line which is neither blank nor a `;` comment
+fn first_record(mut data: &[u8]) -> &[u8] {
+    loop {
+        data = data.trim_ascii_start();
+        if !data.starts_with(b";") {
+            return data;
+        }
+        data = match data.iter().position(|byte| *byte == b'\n') {
+            Some(end) => &data[end..],
+            None => &[],
+        };
+    }
+}
+
+impl fmt::Display for ImageFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            ImageFormat::Binary => "binary",
+            ImageFormat::IntelHex => "Intel HEX",
+            ImageFormat::Elf => "ELF",
+            ImageFormat::Uf2 => "UF2",
+        })
+    }
+}
+
+/// Contiguous bytes of an image at their address
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Segment {
+    pub address: u32,
+    pub data: Vec<u8>,
+}
+
+impl Segment {
+    /// Address right after the segment
+    pub fn end(&self) -> u64 {
+        self.address as u64 + self.data.len() as u64
+    }
+}
+
+/// Parse an image into its segments, sorted by address, in the format given
+/// or detected. A raw binary is a single segment at `address`, which
+/// defaults to 0, while other formats carry their own addresses and reject
+/// it.
+pub fn parse_image(
+    path: &Path,
+    data: Vec<u8>,
+    address: Option<u32>,
+    format: Option<ImageFormat>,
+) -> Result<(ImageFormat, Vec<Segment>), String> {
+    let format = format.unwrap_or_else(|| ImageFormat::detect(path, &data));
+
+    let segments = match format {
+        ImageFormat::Binary => {
+            return Ok((
+                format,
+                vec![Segment {
+                    address: address.unwrap_or(0),
+                    data,
+                }],
+            ));
+        }
+        _ if address.is_some() => {
+            return Err(format!(
+                "{} images carry their own addresses, no address can be given",
+                format
+            ));
+        }
+        ImageFormat::IntelHex => parse_intel_hex(&data)?,
+        ImageFormat::Elf => parse_elf(&data)?,
+        ImageFormat::Uf2 => parse_uf2(&data)?,
+    };
+
+    if segments.is_empty() {
+        return Err(format!("{} image has no data to flash", format));
+    }
+
+    Ok((format, merge_segments(segments)?))
+}
+
+/// Map the segments of an image to offsets in flash, checking they fit in
+/// its `size` if known. Raw binaries are addressed by offsets already, while
+/// Intel HEX, ELF and UF2 images carry the addresses flash is mapped at from
+/// `base`, and can't be mapped without both the base and the size.
+pub fn map_to_flash(
+    format: ImageFormat,
+    segments: Vec<Segment>,
+    base: Option<u32>,
+    size: Option<u32>,
+) -> Result<Vec<Segment>, String> {
+    let base = match (format, base, size) {
+        (ImageFormat::Binary, _, _) => 0,
+        (_, Some(base), Some(_)) => base,
+        _ => {
+            return Err(format!(
+                "{} images carry memory addresses, set the `base` and `size` of flash in the \
+                 manifest to flash them",
+                format
+            ));
+        }
+    };
+
+    segments
+        .into_iter()
+        .map(|segment| {
+            let offset = segment.address.checked_sub(base).ok_or_else(|| {
+                format!(
+                    "Segment at 0x{:08x} is below the flash base 0x{:08x}",
+                    segment.address, base
+                )
+            })?;
+            let end = offset as u64 + segment.data.len() as u64;
+            if let Some(size) = size
+                && end > size as u64
+            {
+                return Err(format!(
+                    "Segment at 0x{:08x} of {} bytes is beyond the {} bytes of flash",
+                    segment.address,
+                    segment.data.len(),
+                    size
+                ));
+            }
+
+            Ok(Segment {
+                address: offset,
+                data: segment.data,
+            })
+        })
+        .collect()
+}
+
+/// Sort the segments by address and join the adjacent ones, failing if any
+/// of them overlap.
+pub fn merge_segments(mut segments: Vec<Segment>) -> Result<Vec<Segment>, String> {
+    segments.sort_by_key(|segment| segment.address);
+
+    let mut merged: Vec<Segment> = Vec::with_capacity(segments.len());
+    for segment in segments {
+        match merged.last_mut() {
+            Some(last) if last.end() > segment.address as u64 => {
+                return Err(format!(
+                    "Segment at 0x{:08x} overlaps with the segment at 0x{:08x}",
+                    segment.address, last.address
+                ));
+            }
+            Some(last) if last.end() == segment.address as u64 => {
+                last.data.extend_from_slice(&segment.data)
+            }
+            _ => merged.push(segment),
+        }
+    }
+
+    Ok(merged)
+}
+
+/// Parse the data records of an Intel HEX image, with extended segment and
+/// linear addresses.
+pub fn parse_intel_hex(data: &[u8]) -> Result<Vec<Segment>, String> {
+    let text = std::str::from_utf8(data).map_err(|_| "Invalid Intel HEX image".to_string())?;
+
+    let mut segments = Vec::new();
+    let mut base = 0u32;
+
+    for (index, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with(';') {
+            continue;
+        }
+
+        let invalid =
+            |reason: &str| format!("Invalid Intel HEX record at line {}: {}", index + 1, reason);
+
+        let hex = line
+            .strip_prefix(':')
+            .ok_or_else(|| invalid("missing ':'"))?;
+        if !hex.len().is_multiple_of(2) || hex.len() < 10 {
+            return Err(invalid("truncated record"));
+        }
+        let bytes = (0..hex.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&hex[i..i + 2], 16))
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|_| invalid("not hex"))?;
+
+        let length = bytes[0] as usize;
+        if bytes.len() != length + 5 {
+            return Err(invalid("length mismatch"));
+        }
+        if bytes.iter().fold(0u8, |sum, byte| sum.wrapping_add(*byte)) != 0 {
+            return Err(invalid("checksum mismatch"));
+        }
+
+        let offset = u16::from_be_bytes([bytes[1], bytes[2]]) as u32;
+        let payload = &bytes[4..4 + length];
+        match bytes[3] {
+            // Data
+            0x00 => segments.push(Segment {
+                address: base
+                    .checked_add(offset)
+                    .ok_or_else(|| invalid("address overflow"))?,
+                data: payload.to_vec(),
+            }),
+            // End of file
+            0x01 => break,
+            // Extended segment address
+            0x02 if length == 2 => {
+                base = (u16::from_be_bytes([payload[0], payload[1]]) as u32) << 4
+            }
+            // Extended linear address
+            0x04 if length == 2 => {
+                base = (u16::from_be_bytes([payload[0], payload[1]]) as u32) << 16
+            }
+            // Start segment and linear addresses, irrelevant to flashing
+            0x03 | 0x05 => (),
+            _ => return Err(invalid("unsupported record")),
+        }
+    }
+
+    Ok(segments)
+}
+
+/// Parse the loadable segments of an ELF image at their physical (load)
+/// addresses. Segments without file content, e.g. `.bss`, are left out.
+pub fn parse_elf(data: &[u8]) -> Result<Vec<Segment>, String> {
+    match FileKind::parse(data) {
+        Ok(FileKind::Elf32) => load_segments::<FileHeader32<Endianness>>(data),
+        Ok(FileKind::Elf64) => load_segments::<FileHeader64<Endianness>>(data),
+        _ => Err("Invalid ELF image: unknown class".to_string()),
+    }
+}
+
+fn load_segments<Elf: FileHeader<Endian = Endianness>>(
+    data: &[u8],
+) -> Result<Vec<Segment>, String> {
+    let invalid = |reason: &dyn fmt::Display| format!("Invalid ELF image: {}", reason);
+
+    let header = Elf::parse(data).map_err(|e| invalid(&e))?;
+    let endian = header.endian().map_err(|e| invalid(&e))?;
+
+    let mut segments = Vec::new();
+    for program in header
+        .program_headers(endian, data)
+        .map_err(|e| invalid(&e))?
+    {
+        if program.p_type(endian) != PT_LOAD || program.p_filesz(endian).into() == 0 {
+            continue;
+        }
+
+        let paddr = program.p_paddr(endian).into();
+        let address = u32::try_from(paddr)
+            .map_err(|_| invalid(&format!("segment at 0x{:x} beyond 32-bit addresses", paddr)))?;
+        let content = program
+            .data(endian, data)
+            .map_err(|_| invalid(&"truncated"))?;
+
+        segments.push(Segment {
+            address,
+            data: content.to_vec(),
+        });
+    }
+
+    Ok(segments)
+}
+
+/// Parse the blocks of an UF2 image meant for the main flash, skipping the
+/// others, e.g. comments.
+pub fn parse_uf2(data: &[u8]) -> Result<Vec<Segment>, String> {
+    if !data.len().is_multiple_of(UF2_BLOCK_SIZE) {
+        return Err("Invalid UF2 image: size isn't a multiple of 512 bytes".to_string());
+    }
+
+    let mut segments = Vec::new();
+    for (index, block) in data.chunks(UF2_BLOCK_SIZE).enumerate() {
+        if read_u32(block, 0) != UF2_MAGIC_START0
+            || read_u32(block, 4) != UF2_MAGIC_START1
+            || read_u32(block, UF2_BLOCK_SIZE - 4) != UF2_MAGIC_END
+        {
+            return Err(format!("Invalid UF2 block {}: bad magic", index));
+        }
+
+        if read_u32(block, 8) & UF2_FLAG_NOT_MAIN_FLASH != 0 {
+            continue;
+        }
+
+        let (address, size) = (read_u32(block, 12), read_u32(block, 16) as usize);
+        if size > UF2_PAYLOAD_SIZE {
+            return Err(format!("Invalid UF2 block {}: payload too large", index));
+        }
+
+        segments.push(Segment {
+            address,
+            data: block[32..32 + size].to_vec(),
+        });
+    }
+
+    Ok(segments)
+}
+
+/// Little endian word at the offset of the data, which must hold it
+fn read_u32(data: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes(data[offset..offset + 4].try_into().unwrap())
+}
@@ -0,0 +1,139 @@
+use std::{
+    fs::{File, OpenOptions},
+    io::{self, Write},
+    path::Path,
+    sync::{Arc, Mutex},
+};
+
+use rmcp::{
+    Peer, RoleServer,
+    model::{LoggingLevel, LoggingMessageNotificationParam},
+};
+use serde_json::Value as JsonValue;
+use tokio::sync::mpsc::{UnboundedReceiver, UnboundedSender, unbounded_channel};
+
+/// Logger that forwards server events to the client as MCP `notifications/message`,
+/// and optionally to a log file.
+///
+/// Messages logged before the client is initialized are queued and delivered
+/// once the peer is attached.
+#[derive(Clone)]
+pub struct Logger {
+    inner: Arc<LoggerInner>,
+}
+
+struct LoggerInner {
+    level: Mutex<LoggingLevel>,
+    file: Option<Mutex<File>>,
+    sender: UnboundedSender<LoggingMessageNotificationParam>,
+    receiver: Mutex<Option<UnboundedReceiver<LoggingMessageNotificationParam>>>,
+}
+
+impl Logger {
+    pub fn new(log_file: Option<&Path>) -> io::Result<Self> {
+        let file = match log_file {
+            Some(path) => Some(Mutex::new(
+                OpenOptions::new().create(true).append(true).open(path)?,
+            )),
+            None => None,
+        };
+
+        let (sender, receiver) = unbounded_channel();
+
+        Ok(Self {
+            inner: Arc::new(LoggerInner {
+                level: Mutex::new(LoggingLevel::Info),
+                file,
+                sender,
+                receiver: Mutex::new(Some(receiver)),
+            }),
+        })
+    }
+
+    /// Set the minimum level of messages sent to the client.
+    pub fn set_level(&self, level: LoggingLevel) {
+        *self.inner.level.lock().unwrap() = level;
+    }
+
+    /// Start delivering messages to the client.
+    pub fn attach(&self, peer: Peer<RoleServer>) {
+        let Some(mut receiver) = self.inner.receiver.lock().unwrap().take() else {
+            return;
+        };
+
+        tokio::spawn(async move {
+            while let Some(param) = receiver.recv().await {
+                if peer.notify_logging_message(param).await.is_err() {
+                    break;
+                }
+            }
+        });
+    }
+
+    pub fn log(&self, level: LoggingLevel, logger: &str, message: impl Into<String>) {
+        let message = message.into();
+
+        if let Some(file) = &self.inner.file {
+            let mut file = file.lock().unwrap();
+            let _ = writeln!(
+                file,
+                "{} [{}] {}: {}",
+                chrono::Local::now().format("%Y-%m-%d %H:%M:%S%.3f"),
+                level_name(level),
+                logger,
+                message
+            );
+            let _ = file.flush();
+        }
+
+        if severity(level) >= severity(*self.inner.level.lock().unwrap()) {
+            let _ = self.inner.sender.send(LoggingMessageNotificationParam {
+                level,
+                logger: Some(logger.to_string()),
+                data: JsonValue::String(message),
+            });
+        }
+    }
+
+    pub fn debug(&self, logger: &str, message: impl Into<String>) {
+        self.log(LoggingLevel::Debug, logger, message);
+    }
+
+    pub fn info(&self, logger: &str, message: impl Into<String>) {
+        self.log(LoggingLevel::Info, logger, message);
+    }
+
+    pub fn warning(&self, logger: &str, message: impl Into<String>) {
+        self.log(LoggingLevel::Warning, logger, message);
+    }
+
+    pub fn error(&self, logger: &str, message: impl Into<String>) {
+        self.log(LoggingLevel::Error, logger, message);
+    }
+}
+
+fn severity(level: LoggingLevel) -> u8 {
+    match level {
+        LoggingLevel::Debug => 0,
+        LoggingLevel::Info => 1,
+        LoggingLevel::Notice => 2,
+        LoggingLevel::Warning => 3,
+        LoggingLevel::Error => 4,
+        LoggingLevel::Critical => 5,
+        LoggingLevel::Alert => 6,
+        LoggingLevel::Emergency => 7,
+    }
+}
+
+fn level_name(level: LoggingLevel) -> &'static str {
+    match level {
+        LoggingLevel::Debug => "debug",
+        LoggingLevel::Info => "info",
+        LoggingLevel::Notice => "notice",
+        LoggingLevel::Warning => "warning",
+        LoggingLevel::Error => "error",
+        LoggingLevel::Critical => "critical",
+        LoggingLevel::Alert => "alert",
+        LoggingLevel::Emergency => "emergency",
+    }
+}
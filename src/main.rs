@@ -2,14 +2,30 @@ mod commands;
 mod commands_flash;
 mod commands_schematic;
 mod commands_serial;
+mod commands_serial_monitor;
+mod commands_serial_scenario;
+mod confirmation;
 mod error;
+mod flash_format;
+mod logging;
 mod manifest;
 mod manifest_executor;
 mod manifest_reader;
 mod manifest_schema;
+mod port_lease;
+mod progress;
 mod schematic;
 mod schematic_lookup;
 mod schematic_reader;
+mod serial_crash;
+mod serial_dictionary;
+mod serial_format;
+mod serial_log;
+mod serial_monitor;
+mod serial_network;
+mod serial_ports;
+mod serial_recorder;
+mod tool_filter;
 
 use std::{env::current_dir, path::PathBuf};
 
@@ -18,7 +34,7 @@ use clap::{Parser, crate_description};
 use commands::Commands;
 use rmcp::{ServiceExt, transport::stdio};
 
-use crate::manifest_reader::ManifestReader;
+use crate::{logging::Logger, manifest_reader::ManifestReader, tool_filter::ToolFilter};
 
 #[derive(Parser)]
 #[command(author, version, about = crate_description!())]
@@ -27,6 +43,26 @@ struct Args {
     #[arg(short, long, default_value = "commands.yaml", value_name = "MANIFEST")]
     manifest: String,
 
+    /// Glob patterns of tools to enable, separated by commas (e.g. "serial_*,flash_list_ports")
+    #[arg(long, value_delimiter = ',', value_name = "PATTERNS")]
+    tools: Vec<String>,
+
+    /// Glob patterns of tools to disable, separated by commas
+    #[arg(long, value_delimiter = ',', value_name = "PATTERNS")]
+    exclude_tools: Vec<String>,
+
+    /// Name of the profile defined in the manifest to select tools with
+    #[arg(long, value_name = "PROFILE")]
+    profile: Option<String>,
+
+    /// Prefix for names of all tools, overrides the namespace of the manifest
+    #[arg(long, value_name = "PREFIX")]
+    prefix: Option<String>,
+
+    /// Path to a file to write server logs to
+    #[arg(long, value_name = "LOG_FILE")]
+    log_file: Option<PathBuf>,
+
     /// Path to the working directory
     #[arg(default_value = ".", value_name = "WORKING_DIRECTORY")]
     working_directory: Option<PathBuf>,
@@ -40,17 +76,53 @@ async fn main() -> Result<()> {
         .working_directory
         .unwrap_or_else(|| current_dir().expect("Failed to get current directory"));
 
+    let logger = Logger::new(args.log_file.as_deref())
+        .map_err(|e| anyhow::anyhow!("Failed to open log file: {}", e))?;
+
     let manifest_path = working_directory.join(&args.manifest);
 
-    let manifest = manifest::Manifest::read_from(manifest_path)
+    let manifest = manifest::Manifest::read_from(manifest_path.clone())
+        .inspect_err(|e| logger.error("manifest", e.to_string()))
         .map_err(|e| anyhow::anyhow!("Failed to load manifest: {}", e))?;
 
-    let service = Commands::new(working_directory, manifest)
-        .serve(stdio())
-        .await
-        .inspect_err(|e| {
-            println!("Error starting server: {}", e);
-        })?;
+    logger.info(
+        "manifest",
+        format!(
+            "Loaded {} commands from {}",
+            manifest.commands.len(),
+            manifest_path.display()
+        ),
+    );
+
+    let profile = match &args.profile {
+        Some(name) => Some(
+            manifest
+                .profiles
+                .get(name)
+                .cloned()
+                .ok_or_else(|| anyhow::anyhow!("Profile '{}' not found in manifest", name))?,
+        ),
+        None => None,
+    };
+
+    let filter = ToolFilter::new(profile, args.tools, args.exclude_tools);
+
+    let namespace = args.prefix.or_else(|| manifest.namespace.clone());
+
+    let commands = Commands::new(
+        working_directory,
+        manifest,
+        logger.clone(),
+        &filter,
+        namespace.as_deref(),
+    )
+    .inspect_err(|e| logger.error("manifest", e))
+    .map_err(|e| anyhow::anyhow!("Failed to load manifest: {}", e))?;
+
+    let service = commands.serve(stdio()).await.inspect_err(|e| {
+        logger.error("server", format!("Error starting server: {}", e));
+        eprintln!("Error starting server: {}", e);
+    })?;
 
     service.waiting().await?;
 
@@ -17,6 +17,54 @@ pub struct Manifest {
 
     /// Schematic options for the manifest
     pub schematic: Option<SchematicOptions>,
+
+    /// Namespace to prefix names of all tools with (e.g. `foo` turns
+    /// `serial_read` into `foo_serial_read`), for running multiple servers
+    /// side by side. Can be overridden with the `--prefix` command line option.
+    pub namespace: Option<String>,
+
+    /// Named subsets of tools, which can be selected with the `--profile`
+    /// command line option
+    #[serde(default)]
+    pub profiles: BTreeMap<String, Profile>,
+
+    /// What to do with commands that require confirmation when the client
+    /// doesn't support elicitation. Defaults to "deny".
+    #[serde(default)]
+    pub confirm_fallback: ConfirmFallback,
+
+    /// Stable aliases of ports (e.g. `debug`, `flash`), resolved to the
+    /// connected USB port matching the attributes. Accepted by every `port`
+    /// parameter of the built-in tools.
+    #[serde(default)]
+    pub ports: BTreeMap<String, PortMatcher>,
+
+    /// Serial test scenarios, each exposed as a tool running its steps
+    /// against a serial port and reporting whether it passed
+    #[serde(default)]
+    pub serial_scenarios: BTreeMap<String, SerialScenario>,
+}
+
+/// Attributes of a USB port to match. Attributes not specified match any
+/// port, strings are compared case-insensitively.
+#[derive(Debug, Clone, Default, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+#[allow(unused)]
+pub struct PortMatcher {
+    /// USB vendor ID in hex, e.g. "10c4"
+    pub vid: Option<String>,
+
+    /// USB product ID in hex, e.g. "ea60"
+    pub pid: Option<String>,
+
+    /// Serial number of the USB device
+    pub serial_number: Option<String>,
+
+    /// Manufacturer of the USB device
+    pub manufacturer: Option<String>,
+
+    /// Product name of the USB device, e.g. "CP2102N USB to UART Bridge"
+    pub product: Option<String>,
 }
 
 #[derive(Debug, Clone, Deserialize, JsonSchema)]
@@ -42,6 +90,12 @@ pub struct CommandSpec {
     /// `.venv`. Can also be a path to a specific venv.
     #[serde(default)]
     pub venv: VirtualEnv,
+
+    /// Whether to ask the user for confirmation before executing the command.
+    /// Should be set for destructive commands, such as flashing or removing
+    /// files.
+    #[serde(default)]
+    pub confirm: bool,
 }
 
 #[derive(Debug, Clone, Deserialize, JsonSchema)]
@@ -96,6 +150,34 @@ impl Default for VirtualEnv {
     }
 }
 
+#[derive(Debug, Clone, Deserialize, JsonSchema)]
+#[allow(unused)]
+pub struct Profile {
+    /// A brief description of the profile
+    pub description: Option<String>,
+
+    /// Glob patterns of tools to enable (e.g. `serial_*`). If not specified,
+    /// all tools are enabled.
+    #[serde(default)]
+    pub tools: Vec<String>,
+
+    /// Glob patterns of tools to disable, takes precedence over `tools`
+    #[serde(default)]
+    pub exclude_tools: Vec<String>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize, JsonSchema)]
+pub enum ConfirmFallback {
+    /// Refuse to execute the command
+    #[default]
+    #[serde(rename = "deny")]
+    Deny,
+
+    /// Execute the command without confirmation
+    #[serde(rename = "allow")]
+    Allow,
+}
+
 #[derive(Debug, Clone, Deserialize, JsonSchema)]
 #[allow(unused)]
 pub struct FlashOptions {
@@ -108,21 +190,171 @@ pub struct FlashOptions {
     /// The baud rate for the flash operation
     #[serde(default = "default_flash_baudrate")]
     pub baudrate: u32,
+
+    /// The address flash is mapped at, which is subtracted from the addresses
+    /// of Intel HEX, ELF and UF2 images to get the offsets in flash, e.g.
+    /// 0x18000000. Required, with `size`, to flash these images.
+    pub base: Option<FlashAddress>,
+
+    /// The size of flash, to reject images beyond its end
+    pub size: Option<FlashAddress>,
+
+    /// Named flash layouts (e.g. `full`, `app_only`), each a list of images
+    /// written at their addresses, selected by the `layout` parameter of
+    /// `flash_write`
+    #[serde(default)]
+    pub layouts: BTreeMap<String, Vec<FlashImage>>,
 }
 
 fn default_flash_baudrate() -> u32 {
     1500000
 }
 
+/// An image to write to flash at a given address
 #[derive(Debug, Clone, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+#[allow(unused)]
+pub struct FlashImage {
+    /// The path to the image, relative to the working directory. Files with
+    /// a `.bin` extension are raw binaries, Intel HEX and UF2 images are
+    /// detected from their extension, and other files from their content,
+    /// falling back to raw binaries.
+    pub path: String,
+
+    /// The address to write a raw binary at, e.g. 0x10000, defaults to 0.
+    /// Intel HEX, ELF and UF2 images carry their own addresses instead.
+    pub address: Option<FlashAddress>,
+
+    /// The format of the image, overriding its detection
+    pub format: Option<ImageFormat>,
+}
+
+/// Format of an image to flash
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, JsonSchema)]
+#[allow(unused)]
+pub enum ImageFormat {
+    /// Raw binary, written at the address given
+    #[serde(rename = "binary")]
+    Binary,
+
+    /// Intel HEX, whose data records carry their addresses
+    #[serde(rename = "intel_hex")]
+    IntelHex,
+
+    /// ELF, whose loadable segments carry their addresses
+    #[serde(rename = "elf")]
+    Elf,
+
+    /// UF2, whose blocks carry their addresses
+    #[serde(rename = "uf2")]
+    Uf2,
+}
+
+/// An address in flash, either a number or a string in hex, e.g. "0x10000"
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Deserialize, JsonSchema)]
+#[serde(try_from = "FlashAddressValue")]
+#[allow(unused)]
+pub struct FlashAddress(pub u32);
+
+#[derive(Deserialize, JsonSchema)]
+#[serde(untagged)]
+enum FlashAddressValue {
+    Number(u32),
+    Text(String),
+}
+
+impl TryFrom<FlashAddressValue> for FlashAddress {
+    type Error = String;
+
+    fn try_from(value: FlashAddressValue) -> Result<Self, Self::Error> {
+        match value {
+            FlashAddressValue::Number(address) => Ok(FlashAddress(address)),
+            FlashAddressValue::Text(text) => {
+                let parsed = match text.strip_prefix("0x").or(text.strip_prefix("0X")) {
+                    Some(hex) => u32::from_str_radix(hex, 16),
+                    None => text.parse(),
+                };
+                parsed
+                    .map(FlashAddress)
+                    .map_err(|_| format!("Invalid flash address: {}", text))
+            }
+        }
+    }
+}
+
+/// Reset method, either "dtr", "rts", or the name of a sequence defined in
+/// `reset_sequences`
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, JsonSchema)]
+#[serde(try_from = "SerialResetMethodValue")]
+#[allow(unused)]
 pub enum SerialResetMethod {
     /// Reset the device by asserting and deasserting DTR
-    #[serde(rename = "dtr")]
     DTR,
 
     /// Reset the device by asserting and deasserting RTS
-    #[serde(rename = "rts")]
     RTS,
+
+    /// Reset the device with a sequence defined in `reset_sequences`
+    Sequence(String),
+}
+
+#[derive(Deserialize, JsonSchema)]
+#[serde(untagged)]
+enum SerialResetMethodValue {
+    Line(SerialResetLine),
+
+    /// Name of a sequence defined in `reset_sequences`
+    Sequence(String),
+}
+
+#[derive(Deserialize, JsonSchema)]
+enum SerialResetLine {
+    /// Reset the device by asserting and deasserting DTR
+    #[serde(rename = "dtr")]
+    Dtr,
+
+    /// Reset the device by asserting and deasserting RTS
+    #[serde(rename = "rts")]
+    Rts,
+}
+
+impl TryFrom<SerialResetMethodValue> for SerialResetMethod {
+    type Error = String;
+
+    fn try_from(value: SerialResetMethodValue) -> Result<Self, Self::Error> {
+        match value {
+            SerialResetMethodValue::Line(SerialResetLine::Dtr) => Ok(SerialResetMethod::DTR),
+            SerialResetMethodValue::Line(SerialResetLine::Rts) => Ok(SerialResetMethod::RTS),
+            SerialResetMethodValue::Sequence(name) if is_reset_line(&name) => Err(format!(
+                "Unknown reset method \"{}\", did you mean \"{}\"?",
+                name,
+                name.to_lowercase()
+            )),
+            SerialResetMethodValue::Sequence(name) => Ok(SerialResetMethod::Sequence(name)),
+        }
+    }
+}
+
+/// Whether the name is one of the reset lines, in any case, so it can't name
+/// a reset sequence
+pub fn is_reset_line(name: &str) -> bool {
+    name.eq_ignore_ascii_case("dtr") || name.eq_ignore_ascii_case("rts")
+}
+
+/// A step of a reset sequence. The lines specified are set first, then the
+/// step waits for the delay, if any.
+#[derive(Debug, Clone, Default, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+#[allow(unused)]
+pub struct ResetStep {
+    /// Level of the DTR line, `true` to assert
+    pub dtr: Option<bool>,
+
+    /// Level of the RTS line, `true` to assert
+    pub rts: Option<bool>,
+
+    /// Milliseconds to wait after setting the lines
+    pub delay: Option<u64>,
 }
 
 #[derive(Debug, Clone, Deserialize, JsonSchema)]
@@ -135,23 +367,238 @@ pub struct SerialOptions {
     #[serde(default = "default_serial_baudrate")]
     pub baudrate: u32,
 
-    /// Reset method. If specified, the tool will reset the device before each read
+    /// Number of data bits, from 5 to 8, default is 8
+    #[serde(default = "default_data_bits")]
+    pub data_bits: u8,
+
+    /// Parity checking, default is "none"
+    #[serde(default)]
+    pub parity: Parity,
+
+    /// Number of stop bits, 1 or 2, default is 1
+    #[serde(default = "default_stop_bits")]
+    pub stop_bits: u8,
+
+    /// Flow control, default is "none"
+    #[serde(default)]
+    pub flow_control: FlowControl,
+
+    /// Reset method, either "dtr", "rts", or the name of a sequence defined in
+    /// `reset_sequences`. If specified, the tool will reset the device before
+    /// each read, unless the read disables it.
     pub reset: Option<SerialResetMethod>,
 
     /// Optional interval of milliseconds between the reset line is asserted and
     /// deasserted. If not specified, the default is 100ms.
     #[serde(default = "default_reset_interval")]
     pub reset_interval: u64,
+
+    /// Named reset sequences, which can be selected by `reset` or used by
+    /// `serial_reset`, e.g. to enter the download mode
+    #[serde(default)]
+    pub reset_sequences: BTreeMap<String, Vec<ResetStep>>,
+
+    /// Text encoding of the output of the device, e.g. "gbk", default is
+    /// "utf-8"
+    #[serde(default = "default_encoding")]
+    pub encoding: String,
+
+    /// Whether to strip ANSI escape sequences (e.g. colors) from the output
+    /// of the device, default is false
+    #[serde(default)]
+    pub strip_ansi: bool,
+
+    /// Line ending appended to text written to the device, default is "lf"
+    #[serde(default)]
+    pub line_ending: LineEnding,
+
+    /// Maximum number of lines kept in the history of a serial monitor,
+    /// default is 10000
+    #[serde(default = "default_monitor_history")]
+    pub monitor_history: usize,
+
+    /// Path to the firmware ELF, relative to the working directory. If
+    /// specified, crash dumps in the output of the device are detected and
+    /// their addresses resolved to functions and source locations.
+    pub elf: Option<PathBuf>,
+
+    /// Path to the `log_dictionary.json` generated by the Zephyr build,
+    /// relative to the working directory. If specified, the output of the
+    /// device is decoded as Zephyr's dictionary based binary logs.
+    pub log_dictionary: Option<PathBuf>,
+
+    /// Options for recording the data received to files
+    #[serde(default)]
+    pub record: RecordOptions,
+}
+
+#[derive(Debug, Clone, Deserialize, JsonSchema)]
+#[allow(unused)]
+pub struct RecordOptions {
+    /// Directory to write the recorded files to, relative to the working
+    /// directory. Default is "logs/serial".
+    #[serde(default = "default_record_dir")]
+    pub dir: PathBuf,
+
+    /// Size in bytes at which to rotate the recorded files
+    pub max_size: Option<u64>,
+
+    /// Interval in seconds at which to rotate the recorded files
+    pub rotate_interval: Option<u64>,
+}
+
+impl Default for RecordOptions {
+    fn default() -> Self {
+        Self {
+            dir: default_record_dir(),
+            max_size: None,
+            rotate_interval: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, Deserialize, JsonSchema)]
+pub enum Parity {
+    /// No parity bit
+    #[default]
+    #[serde(rename = "none")]
+    None,
+
+    /// Odd parity
+    #[serde(rename = "odd")]
+    Odd,
+
+    /// Even parity
+    #[serde(rename = "even")]
+    Even,
+}
+
+#[derive(Debug, Clone, Copy, Default, Deserialize, JsonSchema)]
+pub enum FlowControl {
+    /// No flow control
+    #[default]
+    #[serde(rename = "none")]
+    None,
+
+    /// Software flow control with XON/XOFF characters
+    #[serde(rename = "software")]
+    Software,
+
+    /// Hardware flow control with the RTS/CTS lines
+    #[serde(rename = "hardware")]
+    Hardware,
+}
+
+#[derive(Debug, Clone, Copy, Default, Deserialize, JsonSchema)]
+pub enum LineEnding {
+    /// No line ending
+    #[serde(rename = "none")]
+    None,
+
+    /// Line feed (`\n`)
+    #[default]
+    #[serde(rename = "lf")]
+    Lf,
+
+    /// Carriage return (`\r`)
+    #[serde(rename = "cr")]
+    Cr,
+
+    /// Carriage return and line feed (`\r\n`)
+    #[serde(rename = "crlf")]
+    CrLf,
 }
 
 fn default_serial_baudrate() -> u32 {
     115200
 }
 
+fn default_data_bits() -> u8 {
+    8
+}
+
+fn default_stop_bits() -> u8 {
+    1
+}
+
 fn default_reset_interval() -> u64 {
     100
 }
 
+fn default_encoding() -> String {
+    "utf-8".to_string()
+}
+
+fn default_monitor_history() -> usize {
+    10000
+}
+
+fn default_record_dir() -> PathBuf {
+    "logs/serial".into()
+}
+
+#[derive(Debug, Clone, Deserialize, JsonSchema)]
+#[allow(unused)]
+pub struct SerialScenario {
+    /// A brief description of the scenario
+    pub description: String,
+
+    /// The port to run the scenario on, or its alias. If not specified, the
+    /// port is an argument of the tool.
+    pub port: Option<String>,
+
+    /// The steps to run in order, stopping at the first one failing
+    pub steps: Vec<ScenarioStep>,
+}
+
+/// A step of a scenario, which sets exactly one of `reset`, `send`,
+/// `expect`, `delay` and `assert`. Text of `send` and `assert` is a
+/// template, in which variables captured by `expect` can be used, e.g.
+/// `{{version}}`.
+#[derive(Debug, Clone, Default, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+#[allow(unused)]
+pub struct ScenarioStep {
+    /// Reset the device: `true` for the reset method configured, or the name
+    /// of a reset sequence
+    pub reset: Option<ScenarioReset>,
+
+    /// Text to send to the device, followed by the line ending configured
+    pub send: Option<String>,
+
+    /// Regular expression to wait for in the output of the device received
+    /// since the previous match. Named groups are captured into variables.
+    pub expect: Option<String>,
+
+    /// Timeout in milliseconds of `expect`, defaults to 5000
+    pub timeout: Option<u64>,
+
+    /// Variable to capture the first group of `expect` into, or the whole
+    /// match if it has no group
+    pub capture: Option<String>,
+
+    /// Duration in milliseconds to wait, while receiving output
+    pub delay: Option<u64>,
+
+    /// Text to check, e.g. `{{version}}`, against the `matches` regular
+    /// expression
+    pub assert: Option<String>,
+
+    /// Regular expression `assert` must match
+    pub matches: Option<String>,
+}
+
+#[derive(Debug, Clone, Deserialize, JsonSchema)]
+#[serde(untagged)]
+#[allow(unused)]
+pub enum ScenarioReset {
+    /// Whether to reset with the reset method configured
+    Enabled(bool),
+
+    /// Name of the reset sequence to run
+    Sequence(String),
+}
+
 #[derive(Debug, Clone, Deserialize, JsonSchema)]
 #[allow(unused)]
 pub struct SchematicOptions {
@@ -7,24 +7,19 @@ use std::{
 };
 
 use handlebars::Handlebars;
-use rmcp::Error as McpError;
+use rmcp::ErrorData as McpError;
 use serde_json::Value as JsonValue;
 
 use crate::manifest::{CommandSpec, VirtualEnv};
 
 impl CommandSpec {
-    pub fn execute(
-        &self,
-        handlebars: &Handlebars,
-        args: &JsonValue,
-        cwd: &PathBuf,
-    ) -> Result<(String, String, i32), McpError> {
-        let command = handlebars
+    pub fn render(&self, handlebars: &Handlebars, args: &JsonValue) -> Result<String, McpError> {
+        handlebars
             .render_template(&self.command, args)
-            .map_err(|e| {
-                McpError::invalid_params(format!("Template rendering error: {}", e), None)
-            })?;
+            .map_err(|e| McpError::invalid_params(format!("Template rendering error: {}", e), None))
+    }
 
+    pub fn execute(&self, command: &str, cwd: &PathBuf) -> Result<(String, i32), McpError> {
         let (mut reader, writer) = pipe().map_err(|e| {
             McpError::internal_error(format!("Failed creating stdio pipes: {}", e), None)
         })?;
@@ -35,7 +30,7 @@ impl CommandSpec {
         };
 
         let mut proc = shell
-            .to_command(&command)
+            .to_command(command)
             .current_dir(cwd)
             .envs(self.venv.to_envs(cwd)?)
             .stdout(writer.try_clone().map_err(|e| {
@@ -61,7 +56,7 @@ impl CommandSpec {
 
         let exit_code = status.code().unwrap_or(1);
 
-        Ok((command, output, exit_code))
+        Ok((output, exit_code))
     }
 }
 
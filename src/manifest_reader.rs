@@ -1,6 +1,9 @@
 use std::{fs::read_to_string, path::PathBuf};
 
-use crate::{error::ManifestError, manifest::Manifest};
+use crate::{
+    error::ManifestError,
+    manifest::{Manifest, ScenarioReset, SerialResetMethod, is_reset_line},
+};
 
 pub trait ManifestReader {
     fn read_from(path: PathBuf) -> Result<Self, ManifestError>
@@ -11,10 +14,72 @@ pub trait ManifestReader {
             .map_err(|e| crate::error::ManifestError::FileRead(path.clone(), e))?;
 
         let manifest: Self = serde_yml::from_str(&content)
-            .map_err(|e| crate::error::ManifestError::Parse(path, e))?;
+            .map_err(|e| crate::error::ManifestError::Parse(path.clone(), e))?;
+
+        manifest
+            .validate()
+            .map_err(|e| crate::error::ManifestError::Invalid(path, e))?;
 
         Ok(manifest)
     }
+
+    /// Check what the schema can't express, e.g. references between options
+    fn validate(&self) -> Result<(), String> {
+        Ok(())
+    }
 }
 
-impl ManifestReader for Manifest {}
+impl ManifestReader for Manifest {
+    fn validate(&self) -> Result<(), String> {
+        if let Some(serial) = &self.serial {
+            if let Some(name) = serial
+                .reset_sequences
+                .keys()
+                .find(|name| is_reset_line(name))
+            {
+                return Err(format!(
+                    "Reset sequence '{}' is named after a reset line, pick another name",
+                    name
+                ));
+            }
+
+            if let Some(SerialResetMethod::Sequence(name)) = &serial.reset
+                && !serial.reset_sequences.contains_key(name)
+            {
+                return Err(format!(
+                    "serial.reset: reset sequence '{}' is not defined in reset_sequences",
+                    name
+                ));
+            }
+        }
+
+        for (name, scenario) in &self.serial_scenarios {
+            for (i, step) in scenario.steps.iter().enumerate() {
+                let invalid = |e: String| {
+                    format!(
+                        "Step {} of serial scenario '{}' is invalid: {}",
+                        i + 1,
+                        name,
+                        e
+                    )
+                };
+
+                step.validate().map_err(invalid)?;
+
+                if let Some(ScenarioReset::Sequence(sequence)) = &step.reset
+                    && !self
+                        .serial
+                        .as_ref()
+                        .is_some_and(|serial| serial.reset_sequences.contains_key(sequence))
+                {
+                    return Err(invalid(format!(
+                        "reset sequence '{}' is not defined in reset_sequences",
+                        sequence
+                    )));
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
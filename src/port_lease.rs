@@ -0,0 +1,64 @@
+use std::{
+    collections::BTreeMap,
+    fs,
+    sync::{Arc, Mutex},
+};
+
+use rmcp::ErrorData as McpError;
+
+/// Key identifying a port across its names: the canonical path of local ports,
+/// so that symlinks like `/dev/serial/by-id/*` name the same device, or the
+/// port as given, e.g. network ports.
+pub fn canonical_port(port: &str) -> String {
+    fs::canonicalize(port)
+        .map(|path| path.to_string_lossy().to_string())
+        .unwrap_or_else(|_| port.to_string())
+}
+
+/// Exclusive leases of ports, so that flash and serial tools never open the
+/// same port at once. Ports are keyed by [`canonical_port`].
+#[derive(Clone, Default)]
+pub struct PortLeases {
+    leases: Arc<Mutex<BTreeMap<String, String>>>,
+}
+
+/// A lease of a port, released when dropped
+pub struct PortLease {
+    leases: PortLeases,
+    key: String,
+}
+
+impl PortLeases {
+    /// Lease the port for the holder, e.g. the name of the tool, or fail if
+    /// the port is already held.
+    pub fn acquire(&self, port: &str, holder: &str) -> Result<PortLease, McpError> {
+        let key = canonical_port(port);
+
+        let mut leases = self.leases.lock().unwrap();
+        if let Some(held_by) = leases.get(&key) {
+            return Err(McpError::invalid_request(
+                format!("Port {} is busy, held by {}", port, held_by),
+                None,
+            ));
+        }
+        leases.insert(key.clone(), holder.to_string());
+
+        Ok(PortLease {
+            leases: self.clone(),
+            key,
+        })
+    }
+}
+
+impl PortLease {
+    /// The leases this lease belongs to, e.g. to lease the port again later
+    pub fn leases(&self) -> PortLeases {
+        self.leases.clone()
+    }
+}
+
+impl Drop for PortLease {
+    fn drop(&mut self) {
+        self.leases.leases.lock().unwrap().remove(&self.key);
+    }
+}
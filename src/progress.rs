@@ -0,0 +1,62 @@
+use std::sync::{Arc, Mutex};
+
+use rmcp::{
+    Peer, RoleServer,
+    model::{Meta, ProgressNotificationParam, ProgressToken},
+};
+use tokio::sync::mpsc::{UnboundedSender, unbounded_channel};
+
+/// Reporter for long running tool calls, which forwards progress to the
+/// client as MCP progress notifications.
+///
+/// Progress is only reported if the client supplied a progress token with the
+/// request, otherwise all reports are discarded.
+#[derive(Clone)]
+pub struct Progress {
+    target: Option<(ProgressToken, UnboundedSender<ProgressNotificationParam>)>,
+    last: Arc<Mutex<f64>>,
+}
+
+impl Progress {
+    pub fn new(peer: Peer<RoleServer>, meta: &Meta) -> Self {
+        let target = meta.get_progress_token().map(|token| {
+            let (sender, mut receiver) = unbounded_channel();
+
+            tokio::spawn(async move {
+                while let Some(param) = receiver.recv().await {
+                    if peer.notify_progress(param).await.is_err() {
+                        break;
+                    }
+                }
+            });
+
+            (token, sender)
+        });
+
+        Self {
+            target,
+            last: Arc::new(Mutex::new(f64::MIN)),
+        }
+    }
+
+    /// Report the current progress. As required by MCP, progress must always
+    /// increase, so reports not greater than the previous one are dropped.
+    pub fn report(&self, progress: f64, total: Option<f64>, message: impl Into<String>) {
+        let Some((token, sender)) = &self.target else {
+            return;
+        };
+
+        let mut last = self.last.lock().unwrap();
+        if progress <= *last {
+            return;
+        }
+        *last = progress;
+
+        let _ = sender.send(ProgressNotificationParam {
+            progress_token: token.clone(),
+            progress,
+            total,
+            message: Some(message.into()),
+        });
+    }
+}
@@ -0,0 +1,257 @@
+use std::{fs, path::Path, sync::LazyLock};
+
+use addr2line::Loader;
+use object::{Architecture, Object, ObjectSymbol, SymbolKind};
+use regex::Regex;
+
+/// Maximum number of lines of a crash dump after its first line
+const MAX_CRASH_LINES: usize = 64;
+
+/// Lines starting a crash dump, e.g. Zephyr fatal errors and faults, ESP-IDF
+/// panics and backtraces, and failed assertions
+static CRASH_START: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(
+        r"(?i)(FATAL ERROR|\*{3,} .*FAULT|Guru Meditation|panic'ed|kernel panic|ASSERTION FAIL|assertion .*failed|^\s*Backtrace:|Call Trace:|Exception \(\d+\):)",
+    )
+    .unwrap()
+});
+
+/// Lines ending a crash dump
+static CRASH_END: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"(?i)(Halting system|Rebooting|ELF file SHA256|System halted)").unwrap()
+});
+
+static ADDRESS: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"0x([0-9a-fA-F]{4,16})").unwrap());
+
+/// A function frame an address resolves to. Inlined functions resolve to
+/// multiple frames, innermost first.
+pub struct SymbolFrame {
+    pub function: String,
+    pub location: Option<String>,
+}
+
+/// Resolves addresses to functions and source locations, from the symbols
+/// and the DWARF debug info of a firmware ELF.
+pub struct Symbolizer {
+    loader: Loader,
+    functions: Vec<FunctionSymbol>,
+}
+
+/// A function of the symbol table, covering `address..address + size`
+struct FunctionSymbol {
+    address: u64,
+    size: u64,
+    name: String,
+}
+
+impl Symbolizer {
+    pub fn open(path: &Path) -> Result<Self, String> {
+        let loader = Loader::new(path)
+            .map_err(|e| format!("Failed to load ELF {}: {}", path.display(), e))?;
+
+        let data =
+            fs::read(path).map_err(|e| format!("Failed to read ELF {}: {}", path.display(), e))?;
+        let mut functions = function_symbols(&data).unwrap_or_default();
+        functions.sort_by_key(|function| function.address);
+
+        Ok(Self { loader, functions })
+    }
+
+    /// Resolve the address, if it points into a function of the ELF. Thumb
+    /// addresses (with the lowest bit set) are resolved without that bit.
+    pub fn symbolize(&self, address: u64) -> Option<Vec<SymbolFrame>> {
+        let address = address & !1;
+
+        let mut frames = Vec::new();
+        if let Ok(mut iter) = self.loader.find_frames(address) {
+            while let Ok(Some(frame)) = iter.next() {
+                let function = frame
+                    .function
+                    .as_ref()
+                    .and_then(|function| function.demangle().ok())
+                    .map(|function| function.to_string());
+
+                let location = frame.location.as_ref().and_then(|location| {
+                    let file = location.file?;
+                    Some(match location.line {
+                        Some(line) => format!("{}:{}", file, line),
+                        None => file.to_string(),
+                    })
+                });
+
+                if let Some(function) = function {
+                    frames.push(SymbolFrame { function, location });
+                }
+            }
+        }
+
+        // Fall back to the functions of the symbol table for code without
+        // debug info. Other symbols, e.g. variables, are left out, as the
+        // addresses of a dump pointing to them are only register values.
+        if frames.is_empty() {
+            frames.push(SymbolFrame {
+                function: self.function_at(address)?.name.clone(),
+                location: None,
+            });
+        }
+
+        Some(frames)
+    }
+
+    fn function_at(&self, address: u64) -> Option<&FunctionSymbol> {
+        let index = self
+            .functions
+            .partition_point(|function| function.address <= address);
+        let function = self.functions.get(index.checked_sub(1)?)?;
+
+        let end = function.address.saturating_add(function.size.max(1));
+        (address < end).then_some(function)
+    }
+}
+
+/// Parse the functions of the symbol table of an ELF. The lowest bit of ARM
+/// function addresses, set for Thumb code, is cleared.
+fn function_symbols(data: &[u8]) -> Option<Vec<FunctionSymbol>> {
+    let file = object::File::parse(data).ok()?;
+    let thumb = file.architecture() == Architecture::Arm;
+
+    let functions = file
+        .symbols()
+        .filter(|symbol| symbol.kind() == SymbolKind::Text && symbol.is_definition())
+        .filter_map(|symbol| {
+            Some(FunctionSymbol {
+                address: if thumb {
+                    symbol.address() & !1
+                } else {
+                    symbol.address()
+                },
+                size: symbol.size(),
+                name: symbol.name().ok()?.to_string(),
+            })
+        })
+        .collect();
+
+    Some(functions)
+}
+
+/// A crash dump found in the lines
+pub struct CrashDump {
+    /// Index of the first line of the dump
+    pub start: usize,
+
+    /// Index after the last line of the dump
+    pub end: usize,
+
+    /// Addresses found in the dump, in order of appearance and deduplicated
+    pub addresses: Vec<u64>,
+}
+
+/// Find crash dumps in the lines
+pub fn find_crashes<S: AsRef<str>>(lines: &[S]) -> Vec<CrashDump> {
+    let mut crashes = Vec::<CrashDump>::new();
+
+    let mut i = 0;
+    while i < lines.len() {
+        if !CRASH_START.is_match(lines[i].as_ref()) {
+            i += 1;
+            continue;
+        }
+
+        let start = i;
+        let mut end = (start + 1 + MAX_CRASH_LINES).min(lines.len());
+        if let Some(offset) = lines[start..end]
+            .iter()
+            .position(|line| CRASH_END.is_match(line.as_ref()))
+        {
+            end = start + offset + 1;
+        }
+
+        let mut addresses = Vec::new();
+        for line in &lines[start..end] {
+            for capture in ADDRESS.captures_iter(line.as_ref()) {
+                if let Ok(address) = u64::from_str_radix(&capture[1], 16)
+                    && !addresses.contains(&address)
+                {
+                    addresses.push(address);
+                }
+            }
+        }
+
+        // Merge dumps split across multiple start markers, e.g. a fault
+        // followed by its backtrace
+        match crashes.last_mut() {
+            Some(last) if last.end >= start => {
+                last.end = end;
+                for address in addresses {
+                    if !last.addresses.contains(&address) {
+                        last.addresses.push(address);
+                    }
+                }
+            }
+            _ => crashes.push(CrashDump {
+                start,
+                end,
+                addresses,
+            }),
+        }
+
+        i = end;
+    }
+
+    crashes
+}
+
+/// Describe the crash dumps found in the lines, with the addresses resolved
+/// against the ELF. Addresses not pointing into code (e.g. register values)
+/// are left out. Returns `None` if no crash is found.
+pub fn analyze_crashes<S: AsRef<str>>(symbolizer: &Symbolizer, lines: &[S]) -> Option<String> {
+    let crashes = find_crashes(lines);
+    if crashes.is_empty() {
+        return None;
+    }
+
+    let mut response = String::from("## Crash analysis\n\n");
+    for crash in crashes {
+        response.push_str(&format!(
+            "Crash detected at line {}: `{}`\n\n",
+            crash.start + 1,
+            lines[crash.start].as_ref().trim()
+        ));
+
+        let resolved = crash
+            .addresses
+            .iter()
+            .filter_map(|address| Some((address, symbolizer.symbolize(*address)?)))
+            .collect::<Vec<_>>();
+
+        if resolved.is_empty() {
+            response.push_str("No addresses in the dump resolve to functions in the ELF.\n\n");
+            continue;
+        }
+
+        response.push_str("| Address | Function | Location |\n");
+        response.push_str("|---------|----------|----------|\n");
+        for (address, frames) in resolved {
+            for (i, frame) in frames.iter().enumerate() {
+                let function = if i == 0 {
+                    frame.function.clone()
+                } else {
+                    format!("(inlined into) {}", frame.function)
+                };
+                response.push_str(&format!(
+                    "| {} | {} | {} |\n",
+                    if i == 0 {
+                        format!("0x{:08x}", address)
+                    } else {
+                        String::new()
+                    },
+                    function,
+                    frame.location.as_deref().unwrap_or("-")
+                ));
+            }
+        }
+        response.push('\n');
+    }
+
+    Some(response)
+}
@@ -0,0 +1,784 @@
+use std::{collections::BTreeMap, fs, path::Path};
+
+use base64::{Engine, prelude::BASE64_STANDARD};
+use serde::Deserialize;
+use serde_json::Value as JsonValue;
+
+/// Types of the messages of the dictionary log stream
+const MSG_TYPE_NORMAL: u8 = 0;
+const MSG_TYPE_DROPPED: u8 = 1;
+
+/// Names of the log levels, as printed by Zephyr
+const LEVELS: [&str; 5] = ["", "err", "wrn", "inf", "dbg"];
+
+/// The `log_dictionary.json` database generated by the Zephyr build
+#[derive(Deserialize)]
+struct DatabaseJson {
+    target: TargetJson,
+
+    #[serde(default)]
+    kconfigs: BTreeMap<String, JsonValue>,
+
+    #[serde(default)]
+    log_subsys: LogSubsysJson,
+
+    #[serde(default)]
+    sections: BTreeMap<String, SectionJson>,
+
+    #[serde(default)]
+    string_mappings: BTreeMap<String, String>,
+}
+
+#[derive(Deserialize)]
+struct TargetJson {
+    bits: u32,
+    little_endianness: Option<bool>,
+}
+
+#[derive(Default, Deserialize)]
+struct LogSubsysJson {
+    #[serde(default)]
+    log_instances: BTreeMap<String, LogInstanceJson>,
+}
+
+#[derive(Deserialize)]
+struct LogInstanceJson {
+    name: String,
+}
+
+#[derive(Deserialize)]
+struct SectionJson {
+    start: u64,
+    data_b64: String,
+}
+
+/// The strings and log sources of a firmware built with Zephyr's dictionary
+/// based logging, which leaves them out of the log messages sent.
+pub struct LogDictionary {
+    pointer_size: usize,
+    little_endian: bool,
+    timestamp_size: usize,
+
+    /// Alignment of 64-bit arguments in the packages
+    align_64: usize,
+
+    /// Names of the log sources, keyed by source ID
+    sources: BTreeMap<u64, String>,
+
+    /// Contents of the sections holding strings, keyed by start address
+    sections: Vec<(u64, Vec<u8>)>,
+
+    /// Strings keyed by address, for builds stripping the format strings
+    strings: BTreeMap<u64, String>,
+
+    /// Whether the UART backend sends the messages as hex characters
+    /// (`CONFIG_LOG_BACKEND_UART_OUTPUT_DICTIONARY_HEX`) instead of binary
+    hex_output: bool,
+}
+
+impl LogDictionary {
+    pub fn open(path: &Path) -> Result<Self, String> {
+        let content = fs::read_to_string(path)
+            .map_err(|e| format!("Failed to read log dictionary {}: {}", path.display(), e))?;
+        let database = serde_json::from_str::<DatabaseJson>(&content)
+            .map_err(|e| format!("Failed to parse log dictionary {}: {}", path.display(), e))?;
+
+        let kconfig = |name: &str| match database.kconfigs.get(name) {
+            Some(JsonValue::Bool(enabled)) => *enabled,
+            Some(JsonValue::String(value)) => value == "y",
+            Some(JsonValue::Number(value)) => value.as_u64() != Some(0),
+            _ => false,
+        };
+
+        let pointer_size = match database.target.bits {
+            32 => 4,
+            64 => 8,
+            bits => return Err(format!("Unsupported target of {} bits", bits)),
+        };
+
+        // 64-bit arguments are aligned to 8 bytes, except on 32-bit x86
+        let align_64 = if kconfig("CONFIG_X86") && pointer_size == 4 {
+            4
+        } else {
+            8
+        };
+
+        let sources = database
+            .log_subsys
+            .log_instances
+            .into_iter()
+            .filter_map(|(id, instance)| Some((parse_number(&id)?, instance.name)))
+            .collect();
+
+        let sections = database
+            .sections
+            .into_values()
+            .map(|section| {
+                let data = BASE64_STANDARD
+                    .decode(&section.data_b64)
+                    .map_err(|e| format!("Invalid section data in log dictionary: {}", e))?;
+                Ok((section.start, data))
+            })
+            .collect::<Result<Vec<_>, String>>()?;
+
+        let strings = database
+            .string_mappings
+            .into_iter()
+            .filter_map(|(address, string)| Some((parse_number(&address)?, string)))
+            .collect();
+
+        Ok(Self {
+            pointer_size,
+            little_endian: database.target.little_endianness.unwrap_or(true),
+            timestamp_size: if kconfig("CONFIG_LOG_TIMESTAMP_64BIT") {
+                8
+            } else {
+                4
+            },
+            align_64,
+            sources,
+            sections,
+            strings,
+            hex_output: kconfig("CONFIG_LOG_BACKEND_UART_OUTPUT_DICTIONARY_HEX"),
+        })
+    }
+
+    /// The string at the address in the firmware, if known
+    fn string_at(&self, address: u64) -> Option<String> {
+        if let Some(string) = self.strings.get(&address) {
+            return Some(string.clone());
+        }
+
+        self.sections.iter().find_map(|(start, data)| {
+            let offset = usize::try_from(address.checked_sub(*start)?).ok()?;
+            let data = data.get(offset..)?;
+            let end = data.iter().position(|b| *b == 0)?;
+            Some(String::from_utf8_lossy(&data[..end]).into_owned())
+        })
+    }
+
+    /// Read an unsigned integer of the size at the offset, in the byte order
+    /// of the target
+    fn read(&self, data: &[u8], offset: usize, size: usize) -> Option<u64> {
+        let bytes = data.get(offset..offset.checked_add(size)?)?;
+        let fold = |value: u64, byte: &u8| (value << 8) | *byte as u64;
+        Some(if self.little_endian {
+            bytes.iter().rev().fold(0, fold)
+        } else {
+            bytes.iter().fold(0, fold)
+        })
+    }
+}
+
+/// Parse a decimal or `0x` prefixed hex number
+fn parse_number(number: &str) -> Option<u64> {
+    match number.strip_prefix("0x") {
+        Some(hex) => u64::from_str_radix(hex, 16).ok(),
+        None => number.parse().ok(),
+    }
+}
+
+/// Decodes the binary stream of Zephyr's dictionary based logging (log
+/// parser v3) into lines like `[00001234] <inf> main: message`.
+pub struct DictionaryDecoder {
+    dictionary: LogDictionary,
+
+    /// Bytes received but not decoded yet
+    pending: Vec<u8>,
+
+    /// Bytes skipped as they don't form a valid message, e.g. text printed
+    /// by the boot banner
+    skipped: Vec<u8>,
+
+    /// High nibble of the byte being received, with the hex output
+    nibble: Option<u8>,
+}
+
+/// Result of decoding the message at the start of the pending bytes
+enum Decoded {
+    /// A message of the given length, decoded into lines
+    Message(usize, Vec<String>),
+
+    /// More bytes are needed to decode the message
+    Incomplete,
+
+    /// No valid message starts here
+    Invalid,
+}
+
+impl DictionaryDecoder {
+    pub fn new(dictionary: LogDictionary) -> Self {
+        Self {
+            dictionary,
+            pending: Vec::new(),
+            skipped: Vec::new(),
+            nibble: None,
+        }
+    }
+
+    /// Decode the bytes received, returning the lines of the messages
+    /// completed by them
+    pub fn feed(&mut self, data: &[u8]) -> Vec<String> {
+        if self.dictionary.hex_output {
+            self.feed_hex(data);
+        } else {
+            self.pending.extend_from_slice(data);
+        }
+
+        let mut lines = Vec::new();
+        let mut offset = 0;
+        while offset < self.pending.len() {
+            match self.decode(&self.pending[offset..]) {
+                Decoded::Message(len, message) => {
+                    self.flush_skipped(&mut lines, true);
+                    lines.extend(message);
+                    offset += len;
+                }
+                Decoded::Incomplete => break,
+                Decoded::Invalid => {
+                    self.skipped.push(self.pending[offset]);
+                    offset += 1;
+                }
+            }
+        }
+        self.pending.drain(..offset);
+
+        self.flush_skipped(&mut lines, false);
+        lines
+    }
+
+    /// Convert the hex output back to the bytes of the messages. Anything but
+    /// hex digits, e.g. line breaks, is ignored.
+    fn feed_hex(&mut self, data: &[u8]) {
+        for byte in data {
+            let Some(digit) = (*byte as char).to_digit(16) else {
+                continue;
+            };
+            match self.nibble.take() {
+                Some(high) => self.pending.push((high << 4) | digit as u8),
+                None => self.nibble = Some(digit as u8),
+            }
+        }
+    }
+
+    /// Report the bytes left undecoded once the stream ends, if any
+    pub fn finish(&mut self) -> Vec<String> {
+        let mut lines = Vec::new();
+        self.skipped.append(&mut self.pending);
+        self.flush_skipped(&mut lines, true);
+        lines
+    }
+
+    /// Report the skipped bytes, as text if they are printable (only
+    /// complete lines unless `all` is set), or as a decoding error otherwise
+    fn flush_skipped(&mut self, lines: &mut Vec<String>, all: bool) {
+        if self.skipped.is_empty() {
+            return;
+        }
+
+        let printable = self
+            .skipped
+            .iter()
+            .all(|b| b.is_ascii_graphic() || b" \t\r\n".contains(b));
+
+        if !printable {
+            if all {
+                lines.push(format!(
+                    "<decoding error: skipped {} bytes not forming a valid message>",
+                    self.skipped.len()
+                ));
+                self.skipped.clear();
+            }
+            return;
+        }
+
+        let end = if all {
+            self.skipped.len()
+        } else {
+            match self.skipped.iter().rposition(|b| *b == b'\n') {
+                Some(pos) => pos + 1,
+                None => return,
+            }
+        };
+
+        let text = String::from_utf8_lossy(&self.skipped[..end]).into_owned();
+        lines.extend(
+            text.lines()
+                .map(|line| line.trim_end_matches('\r'))
+                .filter(|line| !line.is_empty())
+                .map(str::to_string),
+        );
+        self.skipped.drain(..end);
+    }
+
+    fn decode(&self, data: &[u8]) -> Decoded {
+        let dictionary = &self.dictionary;
+
+        match data[0] {
+            MSG_TYPE_DROPPED => match dictionary.read(data, 1, 2) {
+                Some(0) => Decoded::Invalid,
+                Some(count) => {
+                    Decoded::Message(3, vec![format!("--- {} messages dropped ---", count)])
+                }
+                None => Decoded::Incomplete,
+            },
+            MSG_TYPE_NORMAL => self.decode_normal(data),
+            _ => Decoded::Invalid,
+        }
+    }
+
+    fn decode_normal(&self, data: &[u8]) -> Decoded {
+        let dictionary = &self.dictionary;
+        let pointer_size = dictionary.pointer_size;
+
+        // Header: type, domain and level, package length, data length, source
+        // ID and timestamp
+        let header_len = 6 + pointer_size + dictionary.timestamp_size;
+        let package_header_len = 2 * pointer_size;
+        if data.len() < header_len + package_header_len {
+            return Decoded::Incomplete;
+        }
+
+        let (domain_level, package_len, data_len, source, timestamp) = (
+            data[1],
+            dictionary.read(data, 2, 2).unwrap() as usize,
+            dictionary.read(data, 4, 2).unwrap() as usize,
+            dictionary.read(data, 6, pointer_size).unwrap(),
+            dictionary
+                .read(data, 6 + pointer_size, dictionary.timestamp_size)
+                .unwrap(),
+        );
+        let level = if dictionary.little_endian {
+            domain_level >> 4
+        } else {
+            domain_level & 0x0f
+        } as usize;
+
+        // Check the message is plausible before waiting for all of it, so
+        // that a corrupted header doesn't swallow the following messages
+        let package = &data[header_len..];
+        let args_len = package[0] as usize * 4;
+        if level >= LEVELS.len() || args_len < package_header_len || args_len > package_len {
+            return Decoded::Invalid;
+        }
+
+        let Some(format) = dictionary
+            .read(package, pointer_size, pointer_size)
+            .and_then(|address| dictionary.string_at(address))
+        else {
+            return Decoded::Invalid;
+        };
+
+        let len = header_len + package_len + data_len;
+        if data.len() < len {
+            return Decoded::Incomplete;
+        }
+
+        let package = &data[header_len..header_len + package_len];
+        let hexdump = &data[header_len + package_len..len];
+
+        let message = match self.format_package(&format, package) {
+            Ok(message) => message,
+            Err(e) => format!("{} <decoding error: {}>", format.trim_end(), e),
+        };
+
+        let source = match dictionary.sources.get(&source) {
+            Some(name) => format!("{}: ", name),
+            None if source == 0 => String::new(),
+            None => format!("<unknown source {}>: ", source),
+        };
+
+        let mut lines = vec![match level {
+            0 => format!("[{:08}] {}{}", timestamp, source, message.trim_end()),
+            level => format!(
+                "[{:08}] <{}> {}{}",
+                timestamp,
+                LEVELS[level],
+                source,
+                message.trim_end()
+            ),
+        }];
+
+        for chunk in hexdump.chunks(16) {
+            let hex = chunk
+                .iter()
+                .map(|byte| format!("{:02x}", byte))
+                .collect::<Vec<_>>()
+                .join(" ");
+            let ascii = chunk
+                .iter()
+                .map(|byte| {
+                    if byte.is_ascii_graphic() || *byte == b' ' {
+                        *byte as char
+                    } else {
+                        '.'
+                    }
+                })
+                .collect::<String>();
+            lines.push(format!("           {:<48}|{}|", hex, ascii));
+        }
+
+        Decoded::Message(len, lines)
+    }
+
+    /// Format the arguments of the cbprintf package with its format string.
+    ///
+    /// The package is made of a header (its length in 32-bit words and the
+    /// number of strings), the format string pointer, the arguments, the
+    /// indexes of the read-only and read-write string arguments, and the
+    /// strings appended, each prefixed by the index of its argument.
+    fn format_package(&self, format: &str, package: &[u8]) -> Result<String, String> {
+        let args_len = package[0] as usize * 4;
+        let (str_cnt, ro_str_cnt, rw_str_cnt) = (
+            package[1] as usize,
+            package[2] as usize,
+            package[3] as usize,
+        );
+
+        let mut appended = BTreeMap::new();
+        let mut offset = args_len + ro_str_cnt + rw_str_cnt;
+        for _ in 0..str_cnt {
+            let index = *package
+                .get(offset)
+                .ok_or("appended strings out of the package")?;
+            let string = &package[offset + 1..];
+            let end = string
+                .iter()
+                .position(|b| *b == 0)
+                .ok_or("unterminated appended string")?;
+            appended.insert(
+                index as usize,
+                String::from_utf8_lossy(&string[..end]).into_owned(),
+            );
+            offset += end + 2;
+        }
+
+        let mut args = PackageArgs {
+            dictionary: &self.dictionary,
+            args: &package[..args_len],
+            offset: 2 * self.dictionary.pointer_size,
+            appended,
+        };
+        format_printf(format, &mut args)
+    }
+}
+
+/// Reads the arguments of a cbprintf package in order
+struct PackageArgs<'a> {
+    dictionary: &'a LogDictionary,
+    args: &'a [u8],
+    offset: usize,
+    appended: BTreeMap<usize, String>,
+}
+
+impl PackageArgs<'_> {
+    fn next(&mut self, size: usize) -> Result<u64, String> {
+        let align = if size == 8 {
+            self.dictionary.align_64
+        } else {
+            size
+        };
+        self.offset = self.offset.next_multiple_of(align);
+
+        let value = self
+            .dictionary
+            .read(self.args, self.offset, size)
+            .ok_or("missing arguments")?;
+        self.offset += size;
+        Ok(value)
+    }
+
+    fn int(&mut self, size: usize, signed: bool) -> Result<u64, String> {
+        let value = self.next(size)?;
+        if signed && size < 8 {
+            let shift = 64 - size * 8;
+            Ok((((value << shift) as i64) >> shift) as u64)
+        } else {
+            Ok(value)
+        }
+    }
+
+    fn double(&mut self) -> Result<f64, String> {
+        Ok(f64::from_bits(self.next(8)?))
+    }
+
+    /// A string argument, either appended to the package (indexed by its
+    /// position in 32-bit words) or found in the dictionary
+    fn string(&mut self) -> Result<String, String> {
+        let pointer_size = self.dictionary.pointer_size;
+        self.offset = self.offset.next_multiple_of(pointer_size);
+        let index = self.offset / 4;
+        let address = self.next(pointer_size)?;
+
+        Ok(self
+            .appended
+            .get(&index)
+            .cloned()
+            .or_else(|| self.dictionary.string_at(address))
+            .unwrap_or_else(|| format!("<string@0x{:x}>", address)))
+    }
+}
+
+/// Format the arguments like the C `printf`
+fn format_printf(format: &str, args: &mut PackageArgs) -> Result<String, String> {
+    let pointer_size = args.dictionary.pointer_size;
+
+    let mut output = String::new();
+    let mut chars = format.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c != '%' {
+            output.push(c);
+            continue;
+        }
+
+        let mut spec = Spec::default();
+        while let Some(flag) = chars.next_if(|c| "-+ #0".contains(*c)) {
+            match flag {
+                '-' => spec.left = true,
+                '+' => spec.plus = true,
+                ' ' => spec.space = true,
+                '#' => spec.alternate = true,
+                _ => spec.zero = true,
+            }
+        }
+
+        if chars.next_if_eq(&'*').is_some() {
+            let width = args.int(4, true)? as i64;
+            spec.left |= width < 0;
+            spec.width = width.unsigned_abs() as usize;
+        } else {
+            while let Some(digit) = chars.next_if(char::is_ascii_digit) {
+                spec.width = spec.width * 10 + digit.to_digit(10).unwrap() as usize;
+            }
+        }
+
+        if chars.next_if_eq(&'.').is_some() {
+            if chars.next_if_eq(&'*').is_some() {
+                let precision = args.int(4, true)? as i64;
+                spec.precision = (precision >= 0).then_some(precision as usize);
+            } else {
+                let mut precision = 0;
+                while let Some(digit) = chars.next_if(char::is_ascii_digit) {
+                    precision = precision * 10 + digit.to_digit(10).unwrap() as usize;
+                }
+                spec.precision = Some(precision);
+            }
+        }
+
+        let mut length = String::new();
+        while let Some(c) = chars.next_if(|c| "hlzjtL".contains(*c)) {
+            length.push(c);
+        }
+        let size = match length.as_str() {
+            "l" | "z" | "t" => pointer_size,
+            "ll" | "j" => 8,
+            "L" => return Err("unsupported long double argument".to_string()),
+            _ => 4,
+        };
+
+        let Some(conversion) = chars.next() else {
+            output.push('%');
+            break;
+        };
+
+        let formatted = match conversion {
+            '%' => "%".to_string(),
+            'd' | 'i' => {
+                let value = args.int(size, true)? as i64;
+                let sign = if value < 0 {
+                    "-"
+                } else if spec.plus {
+                    "+"
+                } else if spec.space {
+                    " "
+                } else {
+                    ""
+                };
+                spec.number(sign, "", &value.unsigned_abs().to_string())
+            }
+            'u' => spec.number("", "", &args.int(size, false)?.to_string()),
+            'o' => {
+                let value = args.int(size, false)?;
+                let prefix = if spec.alternate && value != 0 {
+                    "0"
+                } else {
+                    ""
+                };
+                spec.number("", prefix, &format!("{:o}", value))
+            }
+            'x' | 'X' => {
+                let value = args.int(size, false)?;
+                let prefix = match (spec.alternate && value != 0, conversion) {
+                    (false, _) => "",
+                    (true, 'x') => "0x",
+                    (true, _) => "0X",
+                };
+                let digits = if conversion == 'x' {
+                    format!("{:x}", value)
+                } else {
+                    format!("{:X}", value)
+                };
+                spec.number("", prefix, &digits)
+            }
+            'p' => {
+                let value = args.int(pointer_size, false)?;
+                spec.pad(format!("0x{:x}", value))
+            }
+            'c' => {
+                let value = args.int(4, false)?;
+                spec.pad(char::from_u32(value as u32).unwrap_or('?').to_string())
+            }
+            's' => {
+                let mut string = args.string()?;
+                if let Some(precision) = spec.precision
+                    && let Some((end, _)) = string.char_indices().nth(precision)
+                {
+                    string.truncate(end);
+                }
+                spec.pad(string)
+            }
+            'f' | 'F' | 'e' | 'E' | 'g' | 'G' | 'a' | 'A' => {
+                let value = args.double()?;
+                spec.float(conversion, value)
+            }
+            'n' => {
+                args.int(pointer_size, false)?;
+                String::new()
+            }
+            conversion => format!("%{}", conversion),
+        };
+        output.push_str(&formatted);
+    }
+
+    Ok(output)
+}
+
+/// A printf conversion specification
+#[derive(Default)]
+struct Spec {
+    left: bool,
+    plus: bool,
+    space: bool,
+    alternate: bool,
+    zero: bool,
+    width: usize,
+    precision: Option<usize>,
+}
+
+impl Spec {
+    /// Pad the text to the width with spaces
+    fn pad(&self, text: String) -> String {
+        if self.left {
+            format!("{:<width$}", text, width = self.width)
+        } else {
+            format!("{:>width$}", text, width = self.width)
+        }
+    }
+
+    /// Format a number from its sign, prefix and digits, applying the
+    /// precision as the minimum number of digits and zero padding
+    fn number(&self, sign: &str, prefix: &str, digits: &str) -> String {
+        let digits = match self.precision {
+            Some(0) if digits == "0" => String::new(),
+            Some(precision) => format!("{:0>precision$}", digits),
+            None => digits.to_string(),
+        };
+
+        if self.zero && !self.left && self.precision.is_none() {
+            let width = self.width.saturating_sub(sign.len() + prefix.len());
+            format!("{}{}{:0>width$}", sign, prefix, digits)
+        } else {
+            self.pad(format!("{}{}{}", sign, prefix, digits))
+        }
+    }
+
+    fn float(&self, conversion: char, value: f64) -> String {
+        let precision = self.precision.unwrap_or(6);
+        let upper = conversion.is_ascii_uppercase();
+
+        if !value.is_finite() {
+            let text = if value.is_nan() {
+                "nan"
+            } else if value > 0.0 {
+                "inf"
+            } else {
+                "-inf"
+            };
+            return self.pad(if upper {
+                text.to_uppercase()
+            } else {
+                text.to_string()
+            });
+        }
+
+        let text = match conversion.to_ascii_lowercase() {
+            'f' => format!("{:.*}", precision, value),
+            'g' => {
+                let precision = precision.max(1);
+                let exponent = if value == 0.0 {
+                    0
+                } else {
+                    value.abs().log10().floor() as i32
+                };
+                let text = if exponent < -4 || exponent >= precision as i32 {
+                    exponential(value, precision - 1)
+                } else {
+                    format!("{:.*}", (precision as i32 - 1 - exponent) as usize, value)
+                };
+                if self.alternate {
+                    text
+                } else {
+                    trim_fraction(&text)
+                }
+            }
+            _ => exponential(value, precision),
+        };
+        let text = if upper { text.to_uppercase() } else { text };
+
+        let (sign, digits) = match text.strip_prefix('-') {
+            Some(digits) => ("-", digits),
+            None if self.plus => ("+", text.as_str()),
+            None if self.space => (" ", text.as_str()),
+            None => ("", text.as_str()),
+        };
+
+        if self.zero && !self.left {
+            let width = self.width.saturating_sub(sign.len());
+            format!("{}{:0>width$}", sign, digits)
+        } else {
+            self.pad(format!("{}{}", sign, digits))
+        }
+    }
+}
+
+/// Format the value like `%e`, e.g. `1.500000e+00`
+fn exponential(value: f64, precision: usize) -> String {
+    let text = format!("{:.*e}", precision, value);
+    match text.split_once('e') {
+        Some((mantissa, exponent)) => {
+            let exponent = exponent.parse::<i32>().unwrap_or(0);
+            format!(
+                "{}e{}{:02}",
+                mantissa,
+                if exponent < 0 { '-' } else { '+' },
+                exponent.abs()
+            )
+        }
+        None => text,
+    }
+}
+
+/// Remove trailing zeros of the fraction, like `%g`
+fn trim_fraction(text: &str) -> String {
+    let (number, exponent) = match text.find('e') {
+        Some(pos) => text.split_at(pos),
+        None => (text, ""),
+    };
+
+    let number = if number.contains('.') {
+        number.trim_end_matches('0').trim_end_matches('.')
+    } else {
+        number
+    };
+    format!("{}{}", number, exponent)
+}
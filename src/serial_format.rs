@@ -0,0 +1,147 @@
+use std::{borrow::Cow, sync::LazyLock};
+
+use encoding_rs::Encoding;
+use regex::Regex;
+use rmcp::schemars;
+use serde::Deserialize;
+
+/// Format of the data returned by `serial_read`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize, schemars::JsonSchema)]
+pub enum ReadFormat {
+    /// Timestamped lines of decoded text
+    #[default]
+    #[serde(rename = "text")]
+    Text,
+
+    /// Hex dump of the raw bytes, with offsets and ASCII
+    #[serde(rename = "hex")]
+    Hex,
+
+    /// Base64 encoded raw bytes
+    #[serde(rename = "base64")]
+    Base64,
+}
+
+/// Decodes text received from a serial port
+#[derive(Debug, Clone, Copy)]
+pub struct TextDecoder {
+    encoding: &'static Encoding,
+    strip_ansi: bool,
+}
+
+impl TextDecoder {
+    /// Create a decoder for the encoding label (e.g. "utf-8", "gbk"),
+    /// optionally stripping ANSI escape sequences from the text.
+    pub fn new(encoding: &str, strip_ansi: bool) -> Result<Self, String> {
+        let encoding = Encoding::for_label(encoding.as_bytes())
+            .ok_or_else(|| format!("Unknown encoding '{}'", encoding))?;
+
+        Ok(Self {
+            encoding,
+            strip_ansi,
+        })
+    }
+
+    /// Decode the bytes, replacing malformed sequences
+    pub fn decode(&self, bytes: &[u8]) -> String {
+        let (text, _) = self.encoding.decode_without_bom_handling(bytes);
+        if self.strip_ansi {
+            strip_ansi(&text).into_owned()
+        } else {
+            text.into_owned()
+        }
+    }
+}
+
+/// Splits the data received from a serial port into decoded lines, without
+/// their line endings. Lines are split on raw bytes, so that multi-byte
+/// characters split across reads are decoded as a whole.
+pub struct LineSplitter {
+    decoder: TextDecoder,
+    pending: Vec<u8>,
+}
+
+impl LineSplitter {
+    pub fn new(decoder: TextDecoder) -> Self {
+        Self {
+            decoder,
+            pending: Vec::new(),
+        }
+    }
+
+    /// Feed the data received, returning the lines completed by it
+    pub fn feed(&mut self, data: &[u8]) -> Vec<String> {
+        self.pending.extend_from_slice(data);
+
+        let mut lines = Vec::new();
+        while let Some(newline_pos) = self.pending.iter().position(|b| *b == b'\n') {
+            let line = self.pending.drain(..=newline_pos).collect::<Vec<_>>();
+            lines.push(self.decode(&line));
+        }
+        lines
+    }
+
+    /// The incomplete line received so far, e.g. a prompt, which isn't
+    /// terminated by a newline
+    pub fn partial(&self) -> String {
+        self.decoder.decode(&self.pending)
+    }
+
+    /// Take the incomplete line left when the reading ends, if any
+    pub fn finish(&mut self) -> Option<String> {
+        if self.pending.is_empty() {
+            return None;
+        }
+
+        let line = std::mem::take(&mut self.pending);
+        Some(self.decode(&line))
+    }
+
+    fn decode(&self, line: &[u8]) -> String {
+        self.decoder
+            .decode(line)
+            .trim_end_matches(['\r', '\n'])
+            .to_string()
+    }
+}
+
+/// Strip ANSI escape sequences, e.g. the colors of Zephyr logs
+pub fn strip_ansi(text: &str) -> Cow<'_, str> {
+    static ANSI_ESCAPE: LazyLock<Regex> = LazyLock::new(|| {
+        Regex::new(r"\x1b(?:\[[0-?]*[ -/]*[@-~]|\][^\x07\x1b]*(?:\x07|\x1b\\)|[@-Z\\-_])").unwrap()
+    });
+
+    ANSI_ESCAPE.replace_all(text, "")
+}
+
+/// Hex dump of the bytes, 16 per line with the offset and the printable
+/// ASCII characters, like `hexdump -C`.
+pub fn hex_dump(data: &[u8]) -> String {
+    let mut dump = String::new();
+
+    for (i, chunk) in data.chunks(16).enumerate() {
+        let mut hex = String::new();
+        for (j, byte) in chunk.iter().enumerate() {
+            if j == 8 {
+                hex.push(' ');
+            }
+            hex.push_str(&format!("{:02x} ", byte));
+        }
+
+        let ascii = chunk
+            .iter()
+            .map(|byte| {
+                if byte.is_ascii_graphic() || *byte == b' ' {
+                    *byte as char
+                } else {
+                    '.'
+                }
+            })
+            .collect::<String>();
+
+        dump.push_str(&format!("{:08x}  {:<49} |{}|\n", i * 16, hex, ascii));
+    }
+
+    dump.push_str(&format!("{:08x}\n", data.len()));
+    dump
+}
@@ -0,0 +1,206 @@
+use std::{collections::BTreeMap, fmt, sync::LazyLock};
+
+use regex::Regex;
+use rmcp::schemars;
+use serde::Deserialize;
+
+/// Zephyr logs, e.g. `[00:00:01.234,000] <err> module: message`, also
+/// without the timestamp or as decoded from dictionary based logs
+static ZEPHYR_LOG: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(
+        r"^(?:\[(?P<timestamp>[^\]]+)\]\s*)?<(?P<level>err|wrn|inf|dbg)>\s+(?:(?P<module>[\w.\-/]+):\s)?(?P<message>.*)$",
+    )
+    .unwrap()
+});
+
+/// ESP-IDF logs, e.g. `E (1234) wifi: message`, possibly colored
+static ESP_IDF_LOG: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(
+        r"^(?:\x1b\[[0-9;]*m)?(?P<level>[EWIDV]) \((?P<timestamp>[^)]+)\) (?P<module>[^:]+): (?P<message>.*?)(?:\x1b\[0m)?$",
+    )
+    .unwrap()
+});
+
+/// Zephyr minimal logs, e.g. `E: message`
+static MINIMAL_LOG: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"^(?P<level>[EWID]): (?P<message>.*)$").unwrap());
+
+/// Plain printf logs tagged with a level, e.g. `[ERROR] message` or
+/// `WARN: message`
+static PLAIN_LOG: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(
+        r"(?i)^\s*\[?(?P<level>fatal|error|err|warning|warn|info|debug|dbg|trace|verbose)(?:\]:?|:)\s*(?P<message>.*)$",
+    )
+    .unwrap()
+});
+
+/// Severity of a log line, from the most to the least severe
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Deserialize, schemars::JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum LogLevel {
+    #[serde(alias = "err")]
+    Error,
+
+    #[serde(alias = "wrn", alias = "warn")]
+    Warning,
+
+    #[serde(alias = "inf")]
+    Info,
+
+    #[serde(alias = "dbg")]
+    Debug,
+
+    Verbose,
+}
+
+impl LogLevel {
+    fn parse(tag: &str) -> Option<Self> {
+        Some(match tag.to_lowercase().as_str() {
+            "e" | "err" | "error" | "fatal" => LogLevel::Error,
+            "w" | "wrn" | "warn" | "warning" => LogLevel::Warning,
+            "i" | "inf" | "info" => LogLevel::Info,
+            "d" | "dbg" | "debug" => LogLevel::Debug,
+            "v" | "trace" | "verbose" => LogLevel::Verbose,
+            _ => return None,
+        })
+    }
+}
+
+impl fmt::Display for LogLevel {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            LogLevel::Error => "error",
+            LogLevel::Warning => "warning",
+            LogLevel::Info => "info",
+            LogLevel::Debug => "debug",
+            LogLevel::Verbose => "verbose",
+        })
+    }
+}
+
+/// A line of output parsed as a log record. Lines in no known format have
+/// no level and are kept whole as the message.
+#[derive(Debug, Clone, Copy)]
+pub struct LogRecord<'a> {
+    pub level: Option<LogLevel>,
+    pub module: Option<&'a str>,
+
+    /// Timestamp printed by the device, as is
+    pub timestamp: Option<&'a str>,
+
+    pub message: &'a str,
+}
+
+impl<'a> LogRecord<'a> {
+    pub fn parse(line: &'a str) -> Self {
+        for format in [&ZEPHYR_LOG, &ESP_IDF_LOG, &MINIMAL_LOG, &PLAIN_LOG] {
+            if let Some(captures) = format.captures(line) {
+                let group = |name| captures.name(name).map(|m| m.as_str());
+                return Self {
+                    level: group("level").and_then(LogLevel::parse),
+                    module: group("module"),
+                    timestamp: group("timestamp"),
+                    message: group("message").unwrap_or(line),
+                };
+            }
+        }
+
+        Self {
+            level: None,
+            module: None,
+            timestamp: None,
+            message: line,
+        }
+    }
+}
+
+/// Filters of log lines by level, module and content
+#[derive(Debug, Default)]
+pub struct LogFilter {
+    /// Least severe level kept. Lines without a level are always kept, as
+    /// they are usually banners, shell output or crash dumps.
+    pub min_level: Option<LogLevel>,
+
+    /// Modules kept, if not empty
+    pub modules: Vec<String>,
+
+    /// Modules left out
+    pub exclude_modules: Vec<String>,
+
+    /// Pattern lines must match
+    pub pattern: Option<Regex>,
+}
+
+impl LogFilter {
+    pub fn is_empty(&self) -> bool {
+        self.min_level.is_none()
+            && self.modules.is_empty()
+            && self.exclude_modules.is_empty()
+            && self.pattern.is_none()
+    }
+
+    pub fn matches(&self, line: &str, record: &LogRecord) -> bool {
+        if let (Some(min_level), Some(level)) = (self.min_level, record.level)
+            && level > min_level
+        {
+            return false;
+        }
+
+        let module = |modules: &[String]| {
+            record
+                .module
+                .is_some_and(|module| modules.iter().any(|m| m == module))
+        };
+        if !self.modules.is_empty() && !module(&self.modules) {
+            return false;
+        }
+        if module(&self.exclude_modules) {
+            return false;
+        }
+
+        self.pattern
+            .as_ref()
+            .is_none_or(|pattern| pattern.is_match(line))
+    }
+}
+
+/// Counts of log lines per level, and the first error seen
+#[derive(Debug, Default)]
+pub struct LogSummary {
+    pub counts: BTreeMap<LogLevel, usize>,
+
+    /// Number of lines without a level
+    pub unleveled: usize,
+
+    /// Index of the first error line
+    pub first_error: Option<usize>,
+}
+
+impl LogSummary {
+    pub fn add(&mut self, index: usize, record: &LogRecord) {
+        match record.level {
+            Some(level) => {
+                *self.counts.entry(level).or_default() += 1;
+                if level == LogLevel::Error && self.first_error.is_none() {
+                    self.first_error = Some(index);
+                }
+            }
+            None => self.unleveled += 1,
+        }
+    }
+}
+
+impl fmt::Display for LogSummary {
+    /// Counts per level, e.g. `2 error, 10 info, 3 without level`
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut counts = self
+            .counts
+            .iter()
+            .map(|(level, count)| format!("{} {}", count, level))
+            .collect::<Vec<_>>();
+        if self.unleveled > 0 {
+            counts.push(format!("{} without level", self.unleveled));
+        }
+        f.write_str(&counts.join(", "))
+    }
+}
@@ -0,0 +1,375 @@
+use std::{
+    collections::{BTreeMap, VecDeque},
+    io,
+    path::PathBuf,
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicBool, AtomicU32, Ordering},
+    },
+    thread::{JoinHandle, spawn},
+    time::Duration,
+};
+
+use chrono::{DateTime, Local};
+use regex::Regex;
+use serialport::SerialPort;
+
+use crate::{
+    commands_serial::{SerialLine, serial_open},
+    port_lease::{PortLease, PortLeases, canonical_port},
+    serial_format::{LineSplitter, TextDecoder},
+    serial_recorder::SerialRecorder,
+};
+
+/// A line received by a serial monitor
+#[derive(Debug, Clone)]
+pub struct MonitorLine {
+    /// Sequence number of the line, increasing from 0 since the monitor started
+    pub seq: u64,
+
+    /// Time the line was received
+    pub time: DateTime<Local>,
+
+    /// Content of the line, without the line ending
+    pub text: String,
+}
+
+impl MonitorLine {
+    pub fn to_log(&self) -> String {
+        format!("[{}] {}", self.time.format("%H:%M:%S%.3f"), self.text)
+    }
+}
+
+struct MonitorHistory {
+    lines: VecDeque<MonitorLine>,
+    capacity: usize,
+    next_seq: u64,
+    error: Option<String>,
+    record_error: Option<String>,
+}
+
+impl MonitorHistory {
+    fn push(&mut self, text: String) {
+        if self.lines.len() >= self.capacity {
+            self.lines.pop_front();
+        }
+
+        self.lines.push_back(MonitorLine {
+            seq: self.next_seq,
+            time: Local::now(),
+            text,
+        });
+        self.next_seq += 1;
+    }
+}
+
+/// A serial port kept open in a background thread, recording every line
+/// received into a bounded history.
+pub struct SerialMonitor {
+    pub started: DateTime<Local>,
+    path: String,
+    line: Mutex<SerialLine>,
+    decoder: TextDecoder,
+    leases: PortLeases,
+    history: Arc<Mutex<MonitorHistory>>,
+    stop: Arc<AtomicBool>,
+    baudrate: Arc<AtomicU32>,
+    record_files: Option<Arc<Mutex<Vec<PathBuf>>>>,
+    worker: Worker,
+}
+
+/// State of the background thread of a monitor
+enum Worker {
+    /// Reading from the port, which is leased
+    Running(JoinHandle<Option<SerialRecorder>>, PortLease),
+
+    /// The port is closed and released, e.g. while flashing
+    Paused(Option<SerialRecorder>),
+
+    Stopped,
+}
+
+impl SerialMonitor {
+    /// Start monitoring the device opened from the path, which is leased for
+    /// as long as the monitor runs.
+    pub fn start(
+        path: &str,
+        line: SerialLine,
+        device: Box<dyn SerialPort>,
+        lease: PortLease,
+        capacity: usize,
+        decoder: TextDecoder,
+        recorder: Option<SerialRecorder>,
+    ) -> Self {
+        let history = Arc::new(Mutex::new(MonitorHistory {
+            lines: VecDeque::with_capacity(capacity.min(4096)),
+            capacity: capacity.max(1),
+            next_seq: 0,
+            error: None,
+            record_error: None,
+        }));
+
+        let mut monitor = Self {
+            started: Local::now(),
+            path: path.to_string(),
+            line: Mutex::new(line),
+            decoder,
+            leases: lease.leases(),
+            history,
+            stop: Arc::new(AtomicBool::new(false)),
+            baudrate: Arc::new(AtomicU32::new(0)),
+            record_files: recorder.as_ref().map(|recorder| recorder.files()),
+            worker: Worker::Stopped,
+        };
+        monitor.spawn(device, lease, recorder);
+        monitor
+    }
+
+    fn spawn(
+        &mut self,
+        mut device: Box<dyn SerialPort>,
+        lease: PortLease,
+        mut recorder: Option<SerialRecorder>,
+    ) {
+        let history = self.history.clone();
+        let stop = self.stop.clone();
+        let baudrate = self.baudrate.clone();
+        let decoder = self.decoder;
+
+        stop.store(false, Ordering::Relaxed);
+
+        let handle = spawn(move || {
+            let _ = device.set_timeout(Duration::from_millis(100));
+
+            let mut splitter = LineSplitter::new(decoder);
+            let mut buffer = vec![0; 1024];
+
+            while !stop.load(Ordering::Relaxed) {
+                // Apply the baud rate requested by `set_baudrate`, if any
+                let rate = baudrate.swap(0, Ordering::Relaxed);
+                if rate > 0
+                    && let Err(e) = device.set_baud_rate(rate)
+                {
+                    history.lock().unwrap().error = Some(e.to_string());
+                    break;
+                }
+
+                match device.read(&mut buffer) {
+                    Ok(bytes) if bytes > 0 => {
+                        let mut history = history.lock().unwrap();
+                        if let Some(recorder) = &mut recorder {
+                            recorder.raw(&buffer[..bytes]);
+                        }
+
+                        for line in splitter.feed(&buffer[..bytes]) {
+                            if let Some(recorder) = &mut recorder {
+                                recorder.line(&line);
+                            }
+                            history.push(line);
+                        }
+
+                        if let Some(recorder) = &mut recorder {
+                            recorder.flush();
+                            history.record_error = recorder.error().map(str::to_string);
+                        }
+                    }
+                    Ok(_) => continue, // No data read, continue
+                    Err(e) if e.kind() == io::ErrorKind::TimedOut => continue,
+                    Err(e) => {
+                        history.lock().unwrap().error = Some(e.to_string());
+                        break;
+                    }
+                }
+            }
+
+            if let Some(line) = splitter.finish() {
+                if let Some(recorder) = &mut recorder {
+                    recorder.line(&line);
+                }
+                history.lock().unwrap().push(line);
+            }
+
+            recorder
+        });
+
+        self.worker = Worker::Running(handle, lease);
+    }
+
+    /// Stop the background thread, returning the recorder if any
+    fn join(&mut self) -> Option<SerialRecorder> {
+        self.stop.store(true, Ordering::Relaxed);
+        match std::mem::replace(&mut self.worker, Worker::Stopped) {
+            Worker::Running(handle, _lease) => handle.join().ok().flatten(),
+            Worker::Paused(recorder) => recorder,
+            Worker::Stopped => None,
+        }
+    }
+
+    /// Close and release the port, keeping the history, until resumed.
+    pub fn pause(&mut self) {
+        if matches!(self.worker, Worker::Running(..)) {
+            let recorder = self.join();
+            self.worker = Worker::Paused(recorder);
+        }
+    }
+
+    /// Reopen the port and continue monitoring after a pause.
+    pub fn resume(&mut self) -> Result<(), String> {
+        let Worker::Paused(recorder) = std::mem::replace(&mut self.worker, Worker::Stopped) else {
+            return Ok(());
+        };
+
+        let lease = self
+            .leases
+            .acquire(&self.path, "serial_monitor")
+            .map_err(|e| e.message.to_string())?;
+
+        let line = *self.line.lock().unwrap();
+        let device = serial_open(&self.path, &line).map_err(|e| e.to_string())?;
+
+        self.spawn(device, lease, recorder);
+        Ok(())
+    }
+
+    /// Lines received since the cursor (sequence number), at most `limit`
+    /// lines. Returns the lines, the cursor to continue from, and the number
+    /// of lines after the cursor that were already dropped from the history.
+    pub fn read_since(&self, cursor: u64, limit: usize) -> (Vec<MonitorLine>, u64, u64) {
+        let history = self.history.lock().unwrap();
+
+        let oldest = history
+            .lines
+            .front()
+            .map_or(history.next_seq, |line| line.seq);
+        let dropped = oldest.saturating_sub(cursor);
+
+        let lines = history
+            .lines
+            .iter()
+            .filter(|line| line.seq >= cursor)
+            .take(limit)
+            .cloned()
+            .collect::<Vec<_>>();
+
+        let next = lines.last().map_or(cursor.max(oldest), |line| line.seq + 1);
+
+        (lines, next, dropped)
+    }
+
+    /// Lines in the history matching the pattern, at most `limit` lines from
+    /// the most recent ones.
+    pub fn search(&self, pattern: &Regex, limit: usize) -> Vec<MonitorLine> {
+        let history = self.history.lock().unwrap();
+
+        let mut lines = history
+            .lines
+            .iter()
+            .rev()
+            .filter(|line| pattern.is_match(&line.text))
+            .take(limit)
+            .cloned()
+            .collect::<Vec<_>>();
+        lines.reverse();
+        lines
+    }
+
+    /// Total number of lines received, and the error stopped the monitor if any
+    pub fn status(&self) -> (u64, Option<String>) {
+        let history = self.history.lock().unwrap();
+        (history.next_seq, history.error.clone())
+    }
+
+    /// Files the monitor is recording to, if recording, and the error which
+    /// stopped the recording if any
+    pub fn record_status(&self) -> Option<(Vec<PathBuf>, Option<String>)> {
+        let files = self.record_files.as_ref()?.lock().unwrap().clone();
+        Some((files, self.history.lock().unwrap().record_error.clone()))
+    }
+
+    /// Switch the port to another baud rate, without interrupting the monitor.
+    pub fn set_baudrate(&self, baudrate: u32) {
+        self.line.lock().unwrap().baudrate = baudrate;
+        self.baudrate.store(baudrate, Ordering::Relaxed);
+    }
+
+    /// Stop the background thread and close the port.
+    pub fn stop(&mut self) -> (u64, Option<String>) {
+        self.join();
+        self.status()
+    }
+}
+
+impl Drop for SerialMonitor {
+    /// Join the background thread, so that the port is closed before its lease
+    /// is released.
+    fn drop(&mut self) {
+        self.join();
+    }
+}
+
+/// Serial monitors currently running, keyed by [`canonical_port`], so that
+/// the names of a port all find its monitor
+#[derive(Clone, Default)]
+pub struct SerialMonitors {
+    monitors: Arc<Mutex<BTreeMap<String, SerialMonitor>>>,
+}
+
+impl SerialMonitors {
+    pub fn insert(&self, port: String, monitor: SerialMonitor) {
+        self.monitors
+            .lock()
+            .unwrap()
+            .insert(canonical_port(&port), monitor);
+    }
+
+    pub fn remove(&self, port: &str) -> Option<SerialMonitor> {
+        self.monitors.lock().unwrap().remove(&canonical_port(port))
+    }
+
+    pub fn contains(&self, port: &str) -> bool {
+        self.monitors
+            .lock()
+            .unwrap()
+            .contains_key(&canonical_port(port))
+    }
+
+    pub fn with<T>(&self, port: &str, f: impl FnOnce(&SerialMonitor) -> T) -> Option<T> {
+        self.monitors
+            .lock()
+            .unwrap()
+            .get(&canonical_port(port))
+            .map(f)
+    }
+
+    /// Pause the monitor of the port, if any, releasing the port until the
+    /// returned guard is dropped.
+    pub fn pause(&self, port: &str) -> Option<PausedMonitor> {
+        let port = canonical_port(port);
+        let mut monitors = self.monitors.lock().unwrap();
+        let monitor = monitors.get_mut(&port)?;
+        monitor.pause();
+
+        Some(PausedMonitor {
+            monitors: self.clone(),
+            port,
+        })
+    }
+}
+
+/// A monitor paused by [`SerialMonitors::pause`], resumed when dropped. If
+/// the port can't be reopened, the monitor stops with the error.
+pub struct PausedMonitor {
+    monitors: SerialMonitors,
+    port: String,
+}
+
+impl Drop for PausedMonitor {
+    fn drop(&mut self) {
+        let mut monitors = self.monitors.monitors.lock().unwrap();
+        if let Some(monitor) = monitors.get_mut(&self.port)
+            && let Err(e) = monitor.resume()
+        {
+            monitor.history.lock().unwrap().error = Some(format!("Failed to resume: {}", e));
+        }
+    }
+}
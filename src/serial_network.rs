@@ -0,0 +1,451 @@
+use std::{
+    cell::Cell,
+    io::{self, Read, Write},
+    net::{TcpStream, ToSocketAddrs},
+    time::Duration,
+};
+
+use serialport::{ClearBuffer, DataBits, ErrorKind, FlowControl, Parity, SerialPort, StopBits};
+
+use crate::commands_serial::SerialLine;
+
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+
+// Telnet commands and options
+const IAC: u8 = 255;
+const DONT: u8 = 254;
+const DO: u8 = 253;
+const WONT: u8 = 252;
+const WILL: u8 = 251;
+const SB: u8 = 250;
+const SE: u8 = 240;
+const BINARY: u8 = 0;
+const SUPPRESS_GO_AHEAD: u8 = 3;
+const COM_PORT_OPTION: u8 = 44;
+
+// RFC 2217 client to server commands
+const SET_BAUDRATE: u8 = 1;
+const SET_DATASIZE: u8 = 2;
+const SET_PARITY: u8 = 3;
+const SET_STOPSIZE: u8 = 4;
+const SET_CONTROL: u8 = 5;
+const PURGE_DATA: u8 = 12;
+
+// Values of SET_CONTROL
+const CONTROL_NO_FLOW: u8 = 1;
+const CONTROL_XON_XOFF: u8 = 2;
+const CONTROL_HARDWARE: u8 = 3;
+const CONTROL_BREAK_ON: u8 = 5;
+const CONTROL_BREAK_OFF: u8 = 6;
+const CONTROL_DTR_ON: u8 = 8;
+const CONTROL_DTR_OFF: u8 = 9;
+const CONTROL_RTS_ON: u8 = 11;
+const CONTROL_RTS_OFF: u8 = 12;
+
+/// Whether the port is a network port, i.e. `tcp://host:port` or
+/// `rfc2217://host:port`
+pub fn is_network_port(path: &str) -> bool {
+    path.starts_with("tcp://") || path.starts_with("rfc2217://")
+}
+
+#[derive(Debug, Clone, Copy)]
+enum TelnetState {
+    Data,
+    Iac,
+    Negotiation(u8),
+    Subnegotiation,
+    SubnegotiationIac,
+}
+
+/// A serial port exposed over the network, e.g. by `ser2net`. Raw TCP ports
+/// only carry the data, while RFC 2217 ports also carry the line parameters
+/// and the modem control lines.
+pub struct NetworkPort {
+    url: String,
+    stream: TcpStream,
+    rfc2217: bool,
+    line: SerialLine,
+    timeout: Duration,
+    state: Cell<TelnetState>,
+}
+
+impl NetworkPort {
+    pub fn open(url: &str, line: &SerialLine) -> serialport::Result<Self> {
+        let (rfc2217, address) = if let Some(address) = url.strip_prefix("rfc2217://") {
+            (true, address)
+        } else if let Some(address) = url.strip_prefix("tcp://") {
+            (false, address)
+        } else {
+            return Err(serialport::Error::new(
+                ErrorKind::InvalidInput,
+                format!("Unsupported network port: {}", url),
+            ));
+        };
+
+        let address = address.trim_end_matches('/');
+        let stream = address
+            .to_socket_addrs()?
+            .find_map(|addr| TcpStream::connect_timeout(&addr, CONNECT_TIMEOUT).ok())
+            .ok_or_else(|| {
+                serialport::Error::new(
+                    ErrorKind::NoDevice,
+                    format!("Failed to connect to {}", address),
+                )
+            })?;
+        stream.set_nodelay(true)?;
+
+        let mut port = Self {
+            url: url.to_string(),
+            stream,
+            rfc2217,
+            line: *line,
+            timeout: Duration::from_millis(0),
+            state: Cell::new(TelnetState::Data),
+        };
+        port.set_timeout(Duration::from_millis(100))?;
+
+        if rfc2217 {
+            port.stream.write_all(&[
+                IAC,
+                WILL,
+                COM_PORT_OPTION,
+                IAC,
+                WILL,
+                BINARY,
+                IAC,
+                DO,
+                BINARY,
+                IAC,
+                WILL,
+                SUPPRESS_GO_AHEAD,
+                IAC,
+                DO,
+                SUPPRESS_GO_AHEAD,
+            ])?;
+
+            port.set_baud_rate(line.baudrate)?;
+            port.set_data_bits(line.data_bits)?;
+            port.set_parity(line.parity)?;
+            port.set_stop_bits(line.stop_bits)?;
+            port.set_flow_control(line.flow_control)?;
+            port.write_data_terminal_ready(false)?;
+        }
+
+        Ok(port)
+    }
+
+    /// Send a RFC 2217 command, escaping IAC in the value. No-op for raw TCP
+    /// ports, whose line parameters are configured on the server.
+    fn command(&self, command: u8, value: &[u8]) -> serialport::Result<()> {
+        if !self.rfc2217 {
+            return Ok(());
+        }
+
+        let mut data = vec![IAC, SB, COM_PORT_OPTION, command];
+        for byte in value {
+            data.push(*byte);
+            if *byte == IAC {
+                data.push(IAC);
+            }
+        }
+        data.extend_from_slice(&[IAC, SE]);
+
+        (&self.stream).write_all(&data)?;
+        Ok(())
+    }
+
+    /// Send a modem control command, which raw TCP ports can't carry.
+    fn control(&self, value: u8) -> serialport::Result<()> {
+        if !self.rfc2217 {
+            return Err(serialport::Error::new(
+                ErrorKind::InvalidInput,
+                format!(
+                    "Modem control lines are not available over raw TCP, use rfc2217:// \
+                    instead of {}",
+                    self.url
+                ),
+            ));
+        }
+
+        self.command(SET_CONTROL, &[value])
+    }
+
+    /// Strip telnet commands from the data received, in place, answering the
+    /// option negotiations. Returns the length of the data left.
+    fn decode(&self, buf: &mut [u8]) -> io::Result<usize> {
+        let mut replies = Vec::new();
+        let mut len = 0;
+
+        for i in 0..buf.len() {
+            let byte = buf[i];
+            let state = match (self.state.get(), byte) {
+                (TelnetState::Data, IAC) => TelnetState::Iac,
+                (TelnetState::Data, _) => {
+                    buf[len] = byte;
+                    len += 1;
+                    TelnetState::Data
+                }
+                (TelnetState::Iac, IAC) => {
+                    buf[len] = IAC;
+                    len += 1;
+                    TelnetState::Data
+                }
+                (TelnetState::Iac, WILL | WONT | DO | DONT) => TelnetState::Negotiation(byte),
+                (TelnetState::Iac, SB) => TelnetState::Subnegotiation,
+                (TelnetState::Iac, _) => TelnetState::Data,
+                // The supported options were all requested on open, so the
+                // server is acknowledging them. Refuse the others.
+                (TelnetState::Negotiation(verb), option) => {
+                    if !matches!(option, BINARY | SUPPRESS_GO_AHEAD | COM_PORT_OPTION) {
+                        match verb {
+                            DO => replies.extend_from_slice(&[IAC, WONT, option]),
+                            WILL => replies.extend_from_slice(&[IAC, DONT, option]),
+                            _ => (),
+                        }
+                    }
+                    TelnetState::Data
+                }
+                // Replies of the server to the commands are ignored
+                (TelnetState::Subnegotiation, IAC) => TelnetState::SubnegotiationIac,
+                (TelnetState::Subnegotiation, _) => TelnetState::Subnegotiation,
+                (TelnetState::SubnegotiationIac, SE) => TelnetState::Data,
+                (TelnetState::SubnegotiationIac, _) => TelnetState::Subnegotiation,
+            };
+            self.state.set(state);
+        }
+
+        if !replies.is_empty() {
+            (&self.stream).write_all(&replies)?;
+        }
+
+        Ok(len)
+    }
+}
+
+impl Read for NetworkPort {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        loop {
+            let bytes = match self.stream.read(buf) {
+                Ok(0) => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::ConnectionAborted,
+                        format!("Connection to {} closed", self.url),
+                    ));
+                }
+                Ok(bytes) => bytes,
+                // Read timeouts are reported as `WouldBlock` on Unix
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => {
+                    return Err(io::Error::new(io::ErrorKind::TimedOut, e));
+                }
+                Err(e) => return Err(e),
+            };
+
+            if !self.rfc2217 {
+                return Ok(bytes);
+            }
+
+            // Keep reading if only telnet commands were received
+            let len = self.decode(&mut buf[..bytes])?;
+            if len > 0 {
+                return Ok(len);
+            }
+        }
+    }
+}
+
+impl Write for NetworkPort {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if !self.rfc2217 {
+            return self.stream.write(buf);
+        }
+
+        let mut data = Vec::with_capacity(buf.len());
+        for byte in buf {
+            data.push(*byte);
+            if *byte == IAC {
+                data.push(IAC);
+            }
+        }
+        self.stream.write_all(&data)?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.stream.flush()
+    }
+}
+
+impl SerialPort for NetworkPort {
+    fn name(&self) -> Option<String> {
+        Some(self.url.clone())
+    }
+
+    fn baud_rate(&self) -> serialport::Result<u32> {
+        Ok(self.line.baudrate)
+    }
+
+    fn data_bits(&self) -> serialport::Result<DataBits> {
+        Ok(self.line.data_bits)
+    }
+
+    fn flow_control(&self) -> serialport::Result<FlowControl> {
+        Ok(self.line.flow_control)
+    }
+
+    fn parity(&self) -> serialport::Result<Parity> {
+        Ok(self.line.parity)
+    }
+
+    fn stop_bits(&self) -> serialport::Result<StopBits> {
+        Ok(self.line.stop_bits)
+    }
+
+    fn timeout(&self) -> Duration {
+        self.timeout
+    }
+
+    fn set_baud_rate(&mut self, baud_rate: u32) -> serialport::Result<()> {
+        self.command(SET_BAUDRATE, &baud_rate.to_be_bytes())?;
+        self.line.baudrate = baud_rate;
+        Ok(())
+    }
+
+    fn set_data_bits(&mut self, data_bits: DataBits) -> serialport::Result<()> {
+        self.command(SET_DATASIZE, &[u8::from(data_bits)])?;
+        self.line.data_bits = data_bits;
+        Ok(())
+    }
+
+    fn set_flow_control(&mut self, flow_control: FlowControl) -> serialport::Result<()> {
+        let value = match flow_control {
+            FlowControl::None => CONTROL_NO_FLOW,
+            FlowControl::Software => CONTROL_XON_XOFF,
+            FlowControl::Hardware => CONTROL_HARDWARE,
+        };
+        self.command(SET_CONTROL, &[value])?;
+        self.line.flow_control = flow_control;
+        Ok(())
+    }
+
+    fn set_parity(&mut self, parity: Parity) -> serialport::Result<()> {
+        let value = match parity {
+            Parity::None => 1,
+            Parity::Odd => 2,
+            Parity::Even => 3,
+        };
+        self.command(SET_PARITY, &[value])?;
+        self.line.parity = parity;
+        Ok(())
+    }
+
+    fn set_stop_bits(&mut self, stop_bits: StopBits) -> serialport::Result<()> {
+        let value = match stop_bits {
+            StopBits::One => 1,
+            StopBits::Two => 2,
+        };
+        self.command(SET_STOPSIZE, &[value])?;
+        self.line.stop_bits = stop_bits;
+        Ok(())
+    }
+
+    fn set_timeout(&mut self, timeout: Duration) -> serialport::Result<()> {
+        // A zero timeout would block forever
+        let timeout = timeout.max(Duration::from_millis(1));
+        self.stream.set_read_timeout(Some(timeout))?;
+        self.stream
+            .set_write_timeout(Some(timeout.max(CONNECT_TIMEOUT)))?;
+        self.timeout = timeout;
+        Ok(())
+    }
+
+    fn write_request_to_send(&mut self, level: bool) -> serialport::Result<()> {
+        self.control(if level {
+            CONTROL_RTS_ON
+        } else {
+            CONTROL_RTS_OFF
+        })
+    }
+
+    fn write_data_terminal_ready(&mut self, level: bool) -> serialport::Result<()> {
+        self.control(if level {
+            CONTROL_DTR_ON
+        } else {
+            CONTROL_DTR_OFF
+        })
+    }
+
+    fn read_clear_to_send(&mut self) -> serialport::Result<bool> {
+        Ok(false)
+    }
+
+    fn read_data_set_ready(&mut self) -> serialport::Result<bool> {
+        Ok(false)
+    }
+
+    fn read_ring_indicator(&mut self) -> serialport::Result<bool> {
+        Ok(false)
+    }
+
+    fn read_carrier_detect(&mut self) -> serialport::Result<bool> {
+        Ok(false)
+    }
+
+    fn bytes_to_read(&self) -> serialport::Result<u32> {
+        Ok(0)
+    }
+
+    fn bytes_to_write(&self) -> serialport::Result<u32> {
+        Ok(0)
+    }
+
+    fn clear(&self, buffer_to_clear: ClearBuffer) -> serialport::Result<()> {
+        let value = match buffer_to_clear {
+            ClearBuffer::Input => 1,
+            ClearBuffer::Output => 2,
+            ClearBuffer::All => 3,
+        };
+        self.command(PURGE_DATA, &[value])?;
+
+        // Discard the data already received, still decoding it, so that the
+        // negotiations are answered and commands split across reads aren't
+        // taken for data later
+        if !matches!(buffer_to_clear, ClearBuffer::Output) {
+            self.stream.set_nonblocking(true)?;
+            let mut buffer = [0; 1024];
+            let mut discarded = Ok(());
+            while let Ok(bytes @ 1..) = (&self.stream).read(&mut buffer) {
+                if self.rfc2217 {
+                    discarded = self.decode(&mut buffer[..bytes]).map(|_| ());
+                    if discarded.is_err() {
+                        break;
+                    }
+                }
+            }
+            // Back to blocking reads even if decoding failed, as the port is
+            // still used afterwards
+            self.stream.set_nonblocking(false)?;
+            discarded?;
+        }
+
+        Ok(())
+    }
+
+    fn try_clone(&self) -> serialport::Result<Box<dyn SerialPort>> {
+        Ok(Box::new(Self {
+            url: self.url.clone(),
+            stream: self.stream.try_clone()?,
+            rfc2217: self.rfc2217,
+            line: self.line,
+            timeout: self.timeout,
+            state: Cell::new(TelnetState::Data),
+        }))
+    }
+
+    fn set_break(&self) -> serialport::Result<()> {
+        self.control(CONTROL_BREAK_ON)
+    }
+
+    fn clear_break(&self) -> serialport::Result<()> {
+        self.control(CONTROL_BREAK_OFF)
+    }
+}
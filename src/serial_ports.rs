@@ -0,0 +1,126 @@
+use rmcp::ErrorData as McpError;
+use serialport::{SerialPortType, UsbPortInfo, available_ports};
+
+use crate::{commands::Commands, manifest::PortMatcher};
+
+/// A connected USB serial port
+#[derive(Debug, Clone)]
+pub struct PortInfo {
+    pub name: String,
+    pub usb: UsbPortInfo,
+}
+
+/// List connected USB serial ports. On macOS, only the callout devices
+/// (`/dev/cu.*`) are listed.
+pub fn list_ports() -> serialport::Result<Vec<PortInfo>> {
+    Ok(available_ports()?
+        .into_iter()
+        .filter_map(|port| {
+            if cfg!(target_os = "macos") && !port.port_name.starts_with("/dev/cu.") {
+                return None;
+            }
+
+            match port.port_type {
+                SerialPortType::UsbPort(usb) => Some(PortInfo {
+                    name: port.port_name,
+                    usb,
+                }),
+                _ => None,
+            }
+        })
+        .collect())
+}
+
+impl PortInfo {
+    /// Describe the port with its USB attributes and the aliases matching it,
+    /// e.g. `/dev/ttyUSB0 (VID 10c4, PID ea60, serial 0001, Silicon Labs,
+    /// CP2102 USB to UART) [debug]`
+    pub fn describe(&self, aliases: &[&str]) -> String {
+        let mut attrs = vec![
+            format!("VID {:04x}", self.usb.vid),
+            format!("PID {:04x}", self.usb.pid),
+        ];
+        if let Some(serial_number) = &self.usb.serial_number {
+            attrs.push(format!("serial {}", serial_number));
+        }
+        attrs.extend(self.usb.manufacturer.iter().cloned());
+        attrs.extend(self.usb.product.iter().cloned());
+
+        let mut description = format!("{} ({})", self.name, attrs.join(", "));
+        if !aliases.is_empty() {
+            description.push_str(&format!(" [{}]", aliases.join(", ")));
+        }
+        description
+    }
+}
+
+impl PortMatcher {
+    pub fn matches(&self, usb: &UsbPortInfo) -> bool {
+        fn id_matches(expected: &Option<String>, actual: u16) -> bool {
+            expected.as_ref().is_none_or(|expected| {
+                let expected = expected.trim_start_matches("0x");
+                u16::from_str_radix(expected, 16).is_ok_and(|id| id == actual)
+            })
+        }
+
+        fn str_matches(expected: &Option<String>, actual: &Option<String>) -> bool {
+            expected.as_ref().is_none_or(|expected| {
+                actual
+                    .as_ref()
+                    .is_some_and(|actual| actual.eq_ignore_ascii_case(expected))
+            })
+        }
+
+        id_matches(&self.vid, usb.vid)
+            && id_matches(&self.pid, usb.pid)
+            && str_matches(&self.serial_number, &usb.serial_number)
+            && str_matches(&self.manufacturer, &usb.manufacturer)
+            && str_matches(&self.product, &usb.product)
+    }
+}
+
+impl Commands {
+    /// Aliases defined in the manifest matching the port
+    pub fn port_aliases(&self, port: &PortInfo) -> Vec<&str> {
+        self.manifest
+            .ports
+            .iter()
+            .filter(|(_, matcher)| matcher.matches(&port.usb))
+            .map(|(alias, _)| alias.as_str())
+            .collect()
+    }
+
+    /// Resolve a port alias defined in the manifest to the name of the
+    /// connected port matching it. Names that are not aliases are returned
+    /// as is.
+    pub fn resolve_port(&self, port: &str) -> Result<String, McpError> {
+        let Some(matcher) = self.manifest.ports.get(port) else {
+            return Ok(port.to_string());
+        };
+
+        let ports = list_ports()
+            .map_err(|e| McpError::internal_error(format!("Failed to list ports: {}", e), None))?;
+
+        let mut matched = ports
+            .into_iter()
+            .filter(|info| matcher.matches(&info.usb))
+            .map(|info| info.name)
+            .collect::<Vec<_>>();
+
+        match matched.len() {
+            0 => Err(McpError::invalid_params(
+                format!("No connected port matches the alias '{}'", port),
+                None,
+            )),
+            1 => Ok(matched.remove(0)),
+            _ => Err(McpError::invalid_params(
+                format!(
+                    "Multiple ports match the alias '{}': {}",
+                    port,
+                    matched.join(", ")
+                ),
+                None,
+            )),
+        }
+    }
+}
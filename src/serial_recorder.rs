@@ -0,0 +1,160 @@
+use std::{
+    fs::{self, File},
+    io::{self, BufWriter, Write},
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use chrono::Local;
+
+use crate::manifest::RecordOptions;
+
+/// Records the data received from a serial port into files: the raw bytes
+/// into `.raw` files, and the decoded lines with timestamps into `.log`
+/// files. Both are rotated by size or by time, as configured.
+pub struct SerialRecorder {
+    dir: PathBuf,
+    stem: String,
+    max_size: Option<u64>,
+    rotate_interval: Option<Duration>,
+    raw: BufWriter<File>,
+    log: BufWriter<File>,
+    size: u64,
+    opened: Instant,
+    files: Arc<Mutex<Vec<PathBuf>>>,
+    error: Option<String>,
+}
+
+impl SerialRecorder {
+    /// Create the recorder for the port, with files under `dir` named after
+    /// the port and the current time.
+    pub fn create(dir: &Path, port: &str, opts: &RecordOptions) -> io::Result<Self> {
+        fs::create_dir_all(dir)?;
+
+        let stem = port
+            .chars()
+            .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+            .collect::<String>()
+            .trim_matches('_')
+            .to_string();
+
+        let files = Arc::new(Mutex::new(Vec::new()));
+        let (raw, log) = Self::open(dir, &stem, &files)?;
+
+        Ok(Self {
+            dir: dir.to_path_buf(),
+            stem,
+            max_size: opts.max_size,
+            rotate_interval: opts.rotate_interval.map(Duration::from_secs),
+            raw,
+            log,
+            size: 0,
+            opened: Instant::now(),
+            files,
+            error: None,
+        })
+    }
+
+    fn open(
+        dir: &Path,
+        stem: &str,
+        files: &Arc<Mutex<Vec<PathBuf>>>,
+    ) -> io::Result<(BufWriter<File>, BufWriter<File>)> {
+        let time = Local::now().format("%Y%m%d-%H%M%S");
+
+        // Files rotated within the same second get a sequence suffix
+        let mut name = format!("{}-{}", stem, time);
+        let mut seq = 1;
+        while dir.join(format!("{}.log", name)).exists() {
+            name = format!("{}-{}-{}", stem, time, seq);
+            seq += 1;
+        }
+
+        let log_path = dir.join(format!("{}.log", name));
+        let raw_path = dir.join(format!("{}.raw", name));
+        let raw = BufWriter::new(File::create(&raw_path)?);
+        let log = BufWriter::new(File::create(&log_path)?);
+
+        files.lock().unwrap().extend([log_path, raw_path]);
+        Ok((raw, log))
+    }
+
+    /// Files written so far, the `.log` and `.raw` files of each rotation,
+    /// shared so that they can be listed while another thread is recording.
+    pub fn files(&self) -> Arc<Mutex<Vec<PathBuf>>> {
+        self.files.clone()
+    }
+
+    /// The error which stopped the recording, if any
+    pub fn error(&self) -> Option<&str> {
+        self.error.as_deref()
+    }
+
+    /// Record raw bytes received from the port
+    pub fn raw(&mut self, data: &[u8]) {
+        self.record(|recorder| {
+            recorder.rotate_if_needed()?;
+            recorder.raw.write_all(data)?;
+            recorder.size += data.len() as u64;
+            Ok(())
+        });
+    }
+
+    /// Record a decoded line, with the current time
+    pub fn line(&mut self, text: &str) {
+        self.record(|recorder| {
+            recorder.rotate_if_needed()?;
+            let line = format!("[{}] {}\n", Local::now().format("%F %H:%M:%S%.3f"), text);
+            recorder.log.write_all(line.as_bytes())?;
+            recorder.size += line.len() as u64;
+            Ok(())
+        });
+    }
+
+    /// Flush the files, e.g. before returning their paths
+    pub fn flush(&mut self) {
+        self.record(|recorder| {
+            recorder.raw.flush()?;
+            recorder.log.flush()
+        });
+    }
+
+    /// Run a write. Recording stops at the first error, without interrupting
+    /// the reading.
+    fn record(&mut self, write: impl FnOnce(&mut Self) -> io::Result<()>) {
+        if self.error.is_some() {
+            return;
+        }
+
+        if let Err(e) = write(self) {
+            self.error = Some(e.to_string());
+        }
+    }
+
+    /// Rotate the files if they are full or too old. Only done before writing
+    /// data, so that no empty files are left after the last write.
+    fn rotate_if_needed(&mut self) -> io::Result<()> {
+        let oversized = self.max_size.is_some_and(|max_size| self.size >= max_size);
+        let expired = self
+            .rotate_interval
+            .is_some_and(|interval| self.opened.elapsed() >= interval);
+        if !oversized && !expired {
+            return Ok(());
+        }
+
+        self.raw.flush()?;
+        self.log.flush()?;
+
+        (self.raw, self.log) = Self::open(&self.dir, &self.stem, &self.files)?;
+        self.size = 0;
+        self.opened = Instant::now();
+        Ok(())
+    }
+}
+
+impl Drop for SerialRecorder {
+    fn drop(&mut self) {
+        self.flush();
+    }
+}
@@ -0,0 +1,90 @@
+use crate::manifest::Profile;
+
+/// Filter deciding which tools are registered, from the `--tools` and
+/// `--exclude-tools` options and the selected profile of the manifest.
+#[derive(Debug, Clone, Default)]
+pub struct ToolFilter {
+    profile: Option<Profile>,
+    tools: Vec<String>,
+    exclude_tools: Vec<String>,
+}
+
+impl ToolFilter {
+    pub fn new(profile: Option<Profile>, tools: Vec<String>, exclude_tools: Vec<String>) -> Self {
+        Self {
+            profile,
+            tools,
+            exclude_tools,
+        }
+    }
+
+    /// A tool is allowed if it matches the includes of both the profile and
+    /// the command line (if any), and is not excluded by either of them.
+    pub fn allows(&self, name: &str) -> bool {
+        let (profile_tools, profile_excludes) = match &self.profile {
+            Some(profile) => (profile.tools.as_slice(), profile.exclude_tools.as_slice()),
+            None => (&[][..], &[][..]),
+        };
+
+        includes(profile_tools, name)
+            && includes(&self.tools, name)
+            && !matches_any(profile_excludes, name)
+            && !matches_any(&self.exclude_tools, name)
+    }
+
+    /// Patterns of the profile and the command line matching none of the
+    /// names, which are most likely typos.
+    pub fn unmatched<'a>(&'a self, names: &[&str]) -> Vec<&'a str> {
+        let profile = self
+            .profile
+            .iter()
+            .flat_map(|profile| profile.tools.iter().chain(&profile.exclude_tools));
+        profile
+            .chain(&self.tools)
+            .chain(&self.exclude_tools)
+            .filter(|pattern| !names.iter().any(|name| glob_match(pattern, name)))
+            .map(String::as_str)
+            .collect()
+    }
+}
+
+fn includes(patterns: &[String], name: &str) -> bool {
+    patterns.is_empty() || matches_any(patterns, name)
+}
+
+fn matches_any(patterns: &[String], name: &str) -> bool {
+    patterns.iter().any(|pattern| glob_match(pattern, name))
+}
+
+/// Match a name against a glob pattern, where `*` matches any sequence of
+/// characters and `?` matches a single character.
+fn glob_match(pattern: &str, name: &str) -> bool {
+    let pattern = pattern.chars().collect::<Vec<_>>();
+    let name = name.chars().collect::<Vec<_>>();
+
+    let (mut p, mut n) = (0, 0);
+    let mut backtrack: Option<(usize, usize)> = None;
+
+    while n < name.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                backtrack = Some((p, n));
+                p += 1;
+            }
+            Some(c) if *c == '?' || *c == name[n] => {
+                p += 1;
+                n += 1;
+            }
+            _ => match backtrack {
+                Some((star, matched)) => {
+                    p = star + 1;
+                    n = matched + 1;
+                    backtrack = Some((star, matched + 1));
+                }
+                None => return false,
+            },
+        }
+    }
+
+    pattern[p..].iter().all(|c| *c == '*')
+}
@@ -0,0 +1,546 @@
+//! Test harness for the serial tools: a virtual serial device playing a
+//! scripted Zephyr-like shell, and a client driving the server over stdio.
+
+// Shared by test crates using different parts of it
+#![allow(dead_code)]
+
+use std::{
+    collections::BTreeMap,
+    env, fs,
+    io::{BufRead, BufReader, ErrorKind, Read, Write},
+    net::{TcpListener, TcpStream},
+    path::{Path, PathBuf},
+    process::{Child, ChildStdin, ChildStdout, Command, Stdio},
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+    },
+    thread::{sleep, spawn},
+    time::Duration,
+};
+
+use serde_json::{Value as JsonValue, json};
+use serialport::{SerialPort, TTYPort};
+
+// Telnet commands and options used by RFC 2217
+pub const IAC: u8 = 255;
+pub const DONT: u8 = 254;
+pub const DO: u8 = 253;
+pub const WONT: u8 = 252;
+pub const WILL: u8 = 251;
+const SB: u8 = 250;
+const SE: u8 = 240;
+pub const ECHO: u8 = 1;
+pub const TERMINAL_TYPE: u8 = 24;
+pub const COM_PORT_OPTION: u8 = 44;
+pub const SET_BAUDRATE: u8 = 1;
+const SET_CONTROL: u8 = 5;
+const CONTROL_DTR_ON: u8 = 8;
+const CONTROL_DTR_OFF: u8 = 9;
+const CONTROL_RTS_ON: u8 = 11;
+const CONTROL_RTS_OFF: u8 = 12;
+
+/// Behavior of a virtual device
+#[derive(Clone)]
+pub struct DeviceScript {
+    /// Lines printed when the device boots, i.e. when reset through DTR or
+    /// RTS
+    pub boot_log: Vec<String>,
+
+    /// Prompt printed after the boot log and after every command
+    pub prompt: String,
+
+    /// Whether to echo the characters received
+    pub echo: bool,
+
+    /// Output of the commands, keyed by command line
+    pub commands: BTreeMap<String, String>,
+}
+
+impl Default for DeviceScript {
+    fn default() -> Self {
+        Self {
+            boot_log: vec![
+                "*** Booting Zephyr OS build v3.5.0 ***".to_string(),
+                "[00:00:00.010,000] <inf> main: Hello World! virtual_board".to_string(),
+            ],
+            prompt: "uart:~$ ".to_string(),
+            echo: true,
+            commands: BTreeMap::from([(
+                "kernel version".to_string(),
+                "Zephyr version 3.5.0".to_string(),
+            )]),
+        }
+    }
+}
+
+/// The shell of a virtual device, turning the bytes received into the
+/// bytes to reply with
+struct Shell {
+    script: DeviceScript,
+    line: String,
+    last_cr: bool,
+}
+
+impl Shell {
+    fn boot(&self) -> Vec<u8> {
+        let mut output = String::new();
+        for line in &self.script.boot_log {
+            output.push_str(line);
+            output.push_str("\r\n");
+        }
+        output.push_str(&self.script.prompt);
+        output.into_bytes()
+    }
+
+    fn input(&mut self, data: &[u8]) -> Vec<u8> {
+        let mut output = String::new();
+
+        for byte in data {
+            let c = *byte as char;
+            let after_cr = std::mem::replace(&mut self.last_cr, c == '\r');
+            if c == '\n' && after_cr {
+                continue;
+            }
+
+            if c != '\r' && c != '\n' {
+                if self.script.echo {
+                    output.push(c);
+                }
+                self.line.push(c);
+                continue;
+            }
+
+            if self.script.echo {
+                output.push_str("\r\n");
+            }
+
+            let line = std::mem::take(&mut self.line);
+            let line = line.trim();
+            if !line.is_empty() {
+                match self.script.commands.get(line) {
+                    Some(reply) => output.push_str(reply),
+                    None => output.push_str(&format!("{}: command not found", line)),
+                }
+                output.push_str("\r\n");
+            }
+            output.push_str(&self.script.prompt);
+        }
+
+        output.into_bytes()
+    }
+}
+
+/// State shared between a virtual device and its thread
+#[derive(Default)]
+struct DeviceState {
+    stop: AtomicBool,
+    resets: AtomicUsize,
+    received: Mutex<Vec<u8>>,
+    outbox: Mutex<Vec<u8>>,
+    negotiations: Mutex<Vec<(u8, u8)>>,
+    com_port_commands: Mutex<Vec<Vec<u8>>>,
+}
+
+/// A fake device at the other end of a serial port, run in background until
+/// dropped.
+pub struct VirtualDevice {
+    /// The port to pass to the tools
+    pub port: String,
+
+    state: Arc<DeviceState>,
+
+    /// Slave side of the pseudo-terminal, kept open so that the master side
+    /// doesn't hang up between the tools opening the port
+    _slave: Option<TTYPort>,
+}
+
+impl VirtualDevice {
+    /// A device on a pseudo-terminal pair. Pseudo-terminals don't carry the
+    /// modem control lines, so the device can't be reset.
+    pub fn pty(script: DeviceScript) -> Self {
+        let (mut master, slave) = TTYPort::pair().expect("Failed to create a pseudo-terminal");
+        let port = slave.name().expect("Pseudo-terminal without a name");
+
+        let state = Arc::new(DeviceState::default());
+        let device_state = state.clone();
+        spawn(move || {
+            let mut shell = Shell {
+                script,
+                line: String::new(),
+                last_cr: false,
+            };
+            let _ = master.set_timeout(Duration::from_millis(10));
+
+            let mut buffer = [0; 1024];
+            while !device_state.stop.load(Ordering::Relaxed) {
+                let outbox = std::mem::take(&mut *device_state.outbox.lock().unwrap());
+                if !outbox.is_empty() {
+                    let _ = master.write_all(&outbox);
+                }
+
+                match master.read(&mut buffer) {
+                    Ok(bytes) if bytes > 0 => {
+                        let data = &buffer[..bytes];
+                        device_state
+                            .received
+                            .lock()
+                            .unwrap()
+                            .extend_from_slice(data);
+                        let _ = master.write_all(&shell.input(data));
+                    }
+                    Ok(_) => continue,
+                    Err(e) if e.kind() == ErrorKind::TimedOut => continue,
+                    Err(_) => sleep(Duration::from_millis(10)),
+                }
+            }
+        });
+
+        Self {
+            port,
+            state,
+            _slave: Some(slave),
+        }
+    }
+
+    /// A device served over RFC 2217, which carries DTR and RTS, so that the
+    /// device boots when reset through them.
+    pub fn rfc2217(script: DeviceScript) -> Self {
+        Self::network(script, true)
+    }
+
+    /// A device served over raw TCP, which only carries the data
+    pub fn tcp(script: DeviceScript) -> Self {
+        Self::network(script, false)
+    }
+
+    fn network(script: DeviceScript, telnet: bool) -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").expect("Failed to listen");
+        let scheme = if telnet { "rfc2217" } else { "tcp" };
+        let port = format!("{}://{}", scheme, listener.local_addr().unwrap());
+        listener.set_nonblocking(true).unwrap();
+
+        let state = Arc::new(DeviceState::default());
+        let device_state = state.clone();
+        spawn(move || {
+            let mut shell = Shell {
+                script,
+                line: String::new(),
+                last_cr: false,
+            };
+
+            while !device_state.stop.load(Ordering::Relaxed) {
+                match listener.accept() {
+                    Ok((stream, _)) => serve_network(stream, telnet, &mut shell, &device_state),
+                    Err(_) => sleep(Duration::from_millis(10)),
+                }
+            }
+        });
+
+        Self {
+            port,
+            state,
+            _slave: None,
+        }
+    }
+
+    /// Print the text from the device, e.g. a log line
+    pub fn emit(&self, text: &str) {
+        self.state
+            .outbox
+            .lock()
+            .unwrap()
+            .extend_from_slice(text.as_bytes());
+    }
+
+    /// Number of times the device was reset
+    pub fn resets(&self) -> usize {
+        self.state.resets.load(Ordering::Relaxed)
+    }
+
+    /// Bytes the device received
+    pub fn received(&self) -> Vec<u8> {
+        self.state.received.lock().unwrap().clone()
+    }
+
+    /// Telnet option negotiations the client sent, as verb and option
+    pub fn negotiations(&self) -> Vec<(u8, u8)> {
+        self.state.negotiations.lock().unwrap().clone()
+    }
+
+    /// RFC 2217 commands the client sent, as command and value
+    pub fn com_port_commands(&self) -> Vec<Vec<u8>> {
+        self.state.com_port_commands.lock().unwrap().clone()
+    }
+}
+
+impl Drop for VirtualDevice {
+    fn drop(&mut self) {
+        self.state.stop.store(true, Ordering::Relaxed);
+    }
+}
+
+/// Serve a connection until it closes. Over telnet, i.e. RFC 2217, the
+/// server offers options the client has to refuse, like `ser2net` does, and
+/// the device is reset on the release (falling edge) of DTR or RTS.
+fn serve_network(mut stream: TcpStream, telnet: bool, shell: &mut Shell, state: &DeviceState) {
+    let _ = stream.set_nonblocking(false);
+    let _ = stream.set_read_timeout(Some(Duration::from_millis(10)));
+
+    if telnet
+        && stream
+            .write_all(&[IAC, DO, TERMINAL_TYPE, IAC, WILL, ECHO])
+            .is_err()
+    {
+        return;
+    }
+
+    let (mut dtr, mut rts) = (false, false);
+    let mut iac = false;
+    let mut subnegotiation = None::<Vec<u8>>;
+    let mut negotiation = None::<u8>;
+
+    let mut buffer = [0; 1024];
+    while !state.stop.load(Ordering::Relaxed) {
+        let mut output = std::mem::take(&mut *state.outbox.lock().unwrap());
+
+        let bytes = match stream.read(&mut buffer) {
+            Ok(0) => return,
+            Ok(bytes) => bytes,
+            Err(e) if matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => 0,
+            Err(_) => return,
+        };
+
+        let mut data = Vec::new();
+        for byte in &buffer[..bytes] {
+            let byte = *byte;
+            if !telnet {
+                data.push(byte);
+            } else if let Some(verb) = negotiation.take() {
+                // Accept the options requested by the client silently
+                state.negotiations.lock().unwrap().push((verb, byte));
+            } else if iac {
+                iac = false;
+                match (byte, &mut subnegotiation) {
+                    (IAC, Some(sub)) => sub.push(IAC),
+                    (IAC, None) => data.push(IAC),
+                    (SB, _) => subnegotiation = Some(Vec::new()),
+                    (SE, sub) => {
+                        let Some(sub) = sub.take() else {
+                            continue;
+                        };
+                        if let [COM_PORT_OPTION, command @ ..] = &sub[..] {
+                            state
+                                .com_port_commands
+                                .lock()
+                                .unwrap()
+                                .push(command.to_vec());
+                        }
+                        if let [COM_PORT_OPTION, SET_CONTROL, value] = sub[..] {
+                            let (was_dtr, was_rts) = (dtr, rts);
+                            match value {
+                                CONTROL_DTR_ON => dtr = true,
+                                CONTROL_DTR_OFF => dtr = false,
+                                CONTROL_RTS_ON => rts = true,
+                                CONTROL_RTS_OFF => rts = false,
+                                _ => (),
+                            }
+                            if (was_dtr && !dtr) || (was_rts && !rts) {
+                                state.resets.fetch_add(1, Ordering::Relaxed);
+                                output.extend_from_slice(&shell.boot());
+                            }
+                        }
+                    }
+                    (WILL..=DONT, _) => negotiation = Some(byte),
+                    _ => (),
+                }
+            } else if byte == IAC {
+                iac = true;
+            } else if let Some(sub) = &mut subnegotiation {
+                sub.push(byte);
+            } else {
+                data.push(byte);
+            }
+        }
+
+        if !data.is_empty() {
+            state.received.lock().unwrap().extend_from_slice(&data);
+            output.extend_from_slice(&shell.input(&data));
+        }
+
+        if !output.is_empty() {
+            let mut escaped = Vec::with_capacity(output.len());
+            for byte in output {
+                escaped.push(byte);
+                if telnet && byte == IAC {
+                    escaped.push(IAC);
+                }
+            }
+            if stream.write_all(&escaped).is_err() {
+                return;
+            }
+        }
+    }
+}
+
+/// Result of a tool call
+pub struct ToolResult {
+    pub text: String,
+    pub is_error: bool,
+}
+
+/// The server under test, run with a manifest in a temporary working
+/// directory and driven over stdio.
+pub struct McpServer {
+    child: Child,
+    stdin: ChildStdin,
+    stdout: BufReader<ChildStdout>,
+    next_id: u64,
+    dir: PathBuf,
+}
+
+impl McpServer {
+    /// A temporary working directory with the manifest
+    fn working_dir(manifest: &str) -> PathBuf {
+        static NEXT_DIR: AtomicUsize = AtomicUsize::new(0);
+        let dir = env::temp_dir().join(format!(
+            "commands-mcp-test-{}-{}",
+            std::process::id(),
+            NEXT_DIR.fetch_add(1, Ordering::Relaxed)
+        ));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("commands.yaml"), manifest).unwrap();
+        dir
+    }
+
+    pub fn start(manifest: &str) -> Self {
+        let dir = Self::working_dir(manifest);
+
+        let mut child = Command::new(env!("CARGO_BIN_EXE_commands-mcp"))
+            .arg(&dir)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()
+            .expect("Failed to start the server");
+
+        let mut server = Self {
+            stdin: child.stdin.take().unwrap(),
+            stdout: BufReader::new(child.stdout.take().unwrap()),
+            child,
+            next_id: 1,
+            dir,
+        };
+
+        server
+            .request(
+                "initialize",
+                json!({
+                    "protocolVersion": "2025-06-18",
+                    "capabilities": {},
+                    "clientInfo": { "name": "test", "version": "0.0.0" },
+                }),
+            )
+            .expect("Failed to initialize");
+        server.send(json!({ "jsonrpc": "2.0", "method": "notifications/initialized" }));
+
+        server
+    }
+
+    /// Run the server with a manifest it must refuse to load, returning the
+    /// error it exits with
+    pub fn load_error(manifest: &str) -> String {
+        let dir = Self::working_dir(manifest);
+
+        let output = Command::new(env!("CARGO_BIN_EXE_commands-mcp"))
+            .arg(&dir)
+            .stdin(Stdio::null())
+            .output()
+            .expect("Failed to start the server");
+        let _ = fs::remove_dir_all(&dir);
+
+        assert!(!output.status.success(), "The manifest was loaded");
+        String::from_utf8_lossy(&output.stderr).into_owned()
+    }
+
+    fn send(&mut self, message: JsonValue) {
+        writeln!(self.stdin, "{}", message).unwrap();
+        self.stdin.flush().unwrap();
+    }
+
+    /// Send a request and wait for its response, skipping notifications.
+    /// Returns the result, or the message of the error.
+    fn request(&mut self, method: &str, params: JsonValue) -> Result<JsonValue, String> {
+        let id = self.next_id;
+        self.next_id += 1;
+        self.send(json!({ "jsonrpc": "2.0", "id": id, "method": method, "params": params }));
+
+        loop {
+            let mut line = String::new();
+            if self.stdout.read_line(&mut line).unwrap() == 0 {
+                panic!("Server exited before responding to {}", method);
+            }
+
+            let message = serde_json::from_str::<JsonValue>(&line).unwrap();
+            if message["id"] != id {
+                continue;
+            }
+            if let Some(error) = message.get("error") {
+                return Err(error["message"].as_str().unwrap_or_default().to_string());
+            }
+            return Ok(message["result"].clone());
+        }
+    }
+
+    /// Working directory of the server, holding the manifest
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Names and descriptions of the tools listed by the server
+    pub fn list_tools(&mut self) -> Vec<(String, String)> {
+        let result = self.request("tools/list", json!({})).unwrap();
+
+        result["tools"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .map(|tool| {
+                (
+                    tool["name"].as_str().unwrap_or_default().to_string(),
+                    tool["description"].as_str().unwrap_or_default().to_string(),
+                )
+            })
+            .collect()
+    }
+
+    /// Call the tool, returning its text result, or the message of the error
+    /// if the call is rejected
+    pub fn call(&mut self, tool: &str, arguments: JsonValue) -> Result<ToolResult, String> {
+        let result = self.request(
+            "tools/call",
+            json!({ "name": tool, "arguments": arguments }),
+        )?;
+
+        let text = result["content"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .filter_map(|content| content["text"].as_str())
+            .collect::<Vec<_>>()
+            .join("\n");
+
+        Ok(ToolResult {
+            text,
+            is_error: result["isError"].as_bool().unwrap_or(false),
+        })
+    }
+}
+
+impl Drop for McpServer {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+        let _ = fs::remove_dir_all(&self.dir);
+    }
+}
@@ -0,0 +1,23 @@
+/*
+ * Firmware-like program for the crash analysis tests. The ELFs are built
+ * with:
+ *
+ *   gcc -g -O0 -nostdlib -static -no-pie -fno-asynchronous-unwind-tables \
+ *       -fdebug-prefix-map=$PWD=. -Wl,--build-id=none -Wl,-e,main \
+ *       -o crash.elf crash.c
+ *   strip --strip-debug -o crash-nodebug.elf crash.elf
+ */
+
+volatile int sensor_state;
+
+void sensor_read(void)
+{
+	sensor_state = *(volatile int *)0;
+}
+
+int main(void)
+{
+	sensor_read();
+	for (;;) {
+	}
+}
@@ -0,0 +1,128 @@
+//! Tests of the checks of the flash tools done before connecting to the
+//! device, which needs no device
+
+#![cfg(unix)]
+
+mod common;
+
+use serde_json::json;
+
+use common::McpServer;
+
+const MANIFEST: &str = "
+commands: {}
+flash:
+  enabled: true
+  chip: mars
+  size: 0x100000
+  layouts:
+    full:
+      - path: boot.bin
+        address: 0
+      - path: app.bin
+        address: 0x10000
+    overlapping:
+      - path: boot.bin
+        address: 0
+      - path: app.bin
+        address: 0x1000
+";
+
+/// A server with the images of the layouts, the bootloader spanning 8 KiB
+fn server_with_images() -> McpServer {
+    let server = McpServer::start(MANIFEST);
+    std::fs::write(server.dir().join("boot.bin"), [0u8; 0x2000]).unwrap();
+    std::fs::write(server.dir().join("app.bin"), [0u8; 16]).unwrap();
+    server
+}
+
+/// Call `flash_write`, which must be refused before connecting
+fn flash_write_error(server: &mut McpServer, mut arguments: serde_json::Value) -> String {
+    arguments["port"] = json!("/dev/null");
+    server.call("flash_write", arguments).err().unwrap()
+}
+
+#[test]
+fn flash_write_requires_confirmation() {
+    let mut server = server_with_images();
+
+    // The test client doesn't support elicitation, and the fallback denies
+    let error = flash_write_error(&mut server, json!({ "path": "app.bin" }));
+
+    assert!(
+        error.contains("'flash_write' requires confirmation"),
+        "{}",
+        error
+    );
+}
+
+#[test]
+fn flash_write_resolves_layouts() {
+    let mut server = server_with_images();
+
+    // Resolved and checked, up to the confirmation
+    let error = flash_write_error(&mut server, json!({ "layout": "full" }));
+    assert!(error.contains("requires confirmation"), "{}", error);
+
+    let error = flash_write_error(&mut server, json!({ "layout": "missing" }));
+    assert!(
+        error.contains(
+            "Flash layout 'missing' is not defined, available layouts: full, overlapping"
+        ),
+        "{}",
+        error
+    );
+}
+
+#[test]
+fn flash_write_takes_exactly_one_source() {
+    let mut server = server_with_images();
+
+    for arguments in [
+        json!({}),
+        json!({ "path": "app.bin", "layout": "full" }),
+        json!({ "path": "app.bin", "images": [{ "path": "boot.bin" }] }),
+        json!({ "images": [{ "path": "boot.bin" }], "layout": "full" }),
+    ] {
+        let error = flash_write_error(&mut server, arguments.clone());
+        assert!(
+            error.contains("Exactly one of 'path', 'images' and 'layout'"),
+            "{}: {}",
+            arguments,
+            error
+        );
+    }
+
+    let error = flash_write_error(&mut server, json!({ "layout": "full", "format": "binary" }));
+    assert!(
+        error.contains("'format' only applies to 'path'"),
+        "{}",
+        error
+    );
+}
+
+#[test]
+fn flash_write_rejects_overlapping_images() {
+    let mut server = server_with_images();
+
+    let error = flash_write_error(&mut server, json!({ "layout": "overlapping" }));
+    assert!(
+        error.contains("boot.bin at 0x00000000 overlaps with"),
+        "{}",
+        error
+    );
+    assert!(error.contains("app.bin at 0x00001000"), "{}", error);
+
+    let error = flash_write_error(
+        &mut server,
+        json!({ "images": [
+            { "path": "app.bin", "address": "0x2000" },
+            { "path": "boot.bin", "address": 0x1000 },
+        ] }),
+    );
+    assert!(
+        error.contains("boot.bin at 0x00001000 overlaps with"),
+        "{}",
+        error
+    );
+}
@@ -0,0 +1,436 @@
+//! Tests of the parsing of images to flash, which needs no device
+
+#[allow(unused)]
+#[path = "../src/flash_format.rs"]
+mod flash_format;
+#[allow(unused)]
+#[path = "../src/manifest.rs"]
+mod manifest;
+
+use std::path::Path;
+
+use flash_format::{ImageFormat, Segment, map_to_flash, parse_image};
+
+/// Parse the image as a file of the name, detecting its format
+fn parse(
+    name: &str,
+    data: impl Into<Vec<u8>>,
+    address: Option<u32>,
+) -> Result<(ImageFormat, Vec<Segment>), String> {
+    parse_image(Path::new(name), data.into(), address, None)
+}
+
+/// An Intel HEX record with its checksum
+fn hex_record(kind: u8, offset: u16, data: &[u8]) -> String {
+    let mut bytes = vec![data.len() as u8];
+    bytes.extend_from_slice(&offset.to_be_bytes());
+    bytes.push(kind);
+    bytes.extend_from_slice(data);
+    let checksum = bytes
+        .iter()
+        .fold(0u8, |sum, byte| sum.wrapping_add(*byte))
+        .wrapping_neg();
+    bytes.push(checksum);
+
+    let hex = bytes
+        .iter()
+        .map(|byte| format!("{:02X}", byte))
+        .collect::<String>();
+    format!(":{}\n", hex)
+}
+
+/// A loadable ELF segment: physical address, content, and size in memory
+type ElfSegment<'a> = (u32, &'a [u8], u32);
+
+/// A little endian ELF32 image with program headers for the segments
+fn elf32(segments: &[ElfSegment]) -> Vec<u8> {
+    const EHSIZE: usize = 52;
+    const PHENTSIZE: usize = 32;
+
+    let mut elf = vec![0u8; EHSIZE];
+    elf[..7].copy_from_slice(b"\x7fELF\x01\x01\x01");
+    elf[0x1c..0x20].copy_from_slice(&(EHSIZE as u32).to_le_bytes());
+    elf[0x2a..0x2c].copy_from_slice(&(PHENTSIZE as u16).to_le_bytes());
+    elf[0x2c..0x2e].copy_from_slice(&(segments.len() as u16).to_le_bytes());
+
+    let mut offset = EHSIZE + PHENTSIZE * segments.len();
+    for (paddr, data, memsz) in segments {
+        let mut header = Vec::with_capacity(PHENTSIZE);
+        for field in [
+            1, // PT_LOAD
+            offset as u32,
+            *paddr,
+            *paddr,
+            data.len() as u32,
+            *memsz,
+            5,
+            4,
+        ] {
+            header.extend_from_slice(&field.to_le_bytes());
+        }
+        elf.extend_from_slice(&header);
+        offset += data.len();
+    }
+    for (_, data, _) in segments {
+        elf.extend_from_slice(data);
+    }
+
+    elf
+}
+
+/// An UF2 block of the payload at the address
+fn uf2_block(flags: u32, address: u32, payload: &[u8], index: u32, count: u32) -> Vec<u8> {
+    let mut block = Vec::with_capacity(512);
+    for field in [
+        0x0A32_4655,
+        0x9E5D_5157,
+        flags,
+        address,
+        payload.len() as u32,
+        index,
+        count,
+        0,
+    ] {
+        block.extend_from_slice(&field.to_le_bytes());
+    }
+    block.extend_from_slice(payload);
+    block.resize(508, 0);
+    block.extend_from_slice(&0x0AB1_6F30u32.to_le_bytes());
+    block
+}
+
+#[test]
+fn binary_is_written_at_the_address_given() {
+    let (format, segments) = parse("app.bin", vec![1, 2, 3], Some(0x10000)).unwrap();
+
+    assert_eq!(format, ImageFormat::Binary);
+    assert_eq!(
+        segments,
+        vec![Segment {
+            address: 0x10000,
+            data: vec![1, 2, 3]
+        }]
+    );
+}
+
+#[test]
+fn binary_defaults_to_offset_zero() {
+    let (_, segments) = parse("app.bin", vec![0xff; 4], None).unwrap();
+
+    assert_eq!(segments[0].address, 0);
+}
+
+#[test]
+fn intel_hex_with_extended_linear_addresses() {
+    let hex = [
+        hex_record(0x04, 0, &[0x18, 0x00]),
+        hex_record(0x00, 0x0000, &[1, 2, 3, 4]),
+        hex_record(0x00, 0x0004, &[5, 6]),
+        hex_record(0x04, 0, &[0x18, 0x01]),
+        hex_record(0x00, 0x0010, &[7, 8]),
+        hex_record(0x05, 0, &[0x18, 0x00, 0x01, 0x00]),
+        hex_record(0x01, 0, &[]),
+    ]
+    .concat();
+
+    let (format, segments) = parse("app.hex", hex, None).unwrap();
+
+    assert_eq!(format, ImageFormat::IntelHex);
+    assert_eq!(
+        segments,
+        vec![
+            Segment {
+                address: 0x1800_0000,
+                data: vec![1, 2, 3, 4, 5, 6]
+            },
+            Segment {
+                address: 0x1801_0010,
+                data: vec![7, 8]
+            },
+        ]
+    );
+}
+
+#[test]
+fn intel_hex_with_extended_segment_address() {
+    let hex = [
+        hex_record(0x02, 0, &[0x10, 0x00]),
+        hex_record(0x00, 0x0002, &[0xaa]),
+        hex_record(0x01, 0, &[]),
+    ]
+    .concat();
+
+    let (_, segments) = parse("app.hex", hex, None).unwrap();
+
+    assert_eq!(segments[0].address, 0x10002);
+}
+
+#[test]
+fn intel_hex_rejects_bad_checksum() {
+    let mut hex = hex_record(0x00, 0, &[1, 2, 3]);
+    hex.replace_range(9..11, "FF");
+
+    let error = parse("app.hex", hex, None).unwrap_err();
+
+    assert!(error.contains("checksum"), "{}", error);
+}
+
+#[test]
+fn intel_hex_rejects_overlapping_records() {
+    let hex = [
+        hex_record(0x00, 0x0000, &[1, 2, 3, 4]),
+        hex_record(0x00, 0x0002, &[5, 6]),
+        hex_record(0x01, 0, &[]),
+    ]
+    .concat();
+
+    let error = parse("app.hex", hex, None).unwrap_err();
+
+    assert!(error.contains("overlaps"), "{}", error);
+}
+
+#[test]
+fn elf_loads_segments_at_physical_addresses() {
+    let elf = elf32(&[
+        (0x1800_0000, &[1, 2, 3, 4], 4),
+        // .data, loaded from flash right after the text
+        (0x1800_0004, &[5, 6], 2),
+        // .bss, without content
+        (0x2000_0000, &[], 0x100),
+    ]);
+
+    let (format, segments) = parse("app.elf", elf, None).unwrap();
+
+    assert_eq!(format, ImageFormat::Elf);
+    assert_eq!(
+        segments,
+        vec![Segment {
+            address: 0x1800_0000,
+            data: vec![1, 2, 3, 4, 5, 6]
+        }]
+    );
+}
+
+#[test]
+fn elf_rejects_overlapping_segments() {
+    let elf = elf32(&[(0x1000, &[1, 2, 3, 4], 4), (0x1002, &[5, 6], 2)]);
+
+    let error = parse("app.elf", elf, None).unwrap_err();
+
+    assert!(error.contains("overlaps"), "{}", error);
+}
+
+#[test]
+fn elf_rejects_truncated_image() {
+    let mut elf = elf32(&[(0x1000, &[1, 2, 3, 4], 4)]);
+    elf.truncate(elf.len() - 2);
+
+    let error = parse("app.elf", elf, None).unwrap_err();
+
+    assert!(error.contains("truncated"), "{}", error);
+}
+
+#[test]
+fn addressed_formats_reject_an_address() {
+    let elf = elf32(&[(0x1000, &[1, 2, 3, 4], 4)]);
+
+    let error = parse("app.elf", elf, Some(0x1000)).unwrap_err();
+
+    assert!(error.contains("carry their own addresses"), "{}", error);
+}
+
+#[test]
+fn intel_hex_is_detected_from_its_content() {
+    let hex = format!(
+        "; Generated by the build\n\n  {}{}",
+        hex_record(0x00, 0x0010, &[1, 2]),
+        hex_record(0x01, 0, &[])
+    );
+
+    let (format, segments) = parse("firmware.img", hex, None).unwrap();
+
+    assert_eq!(format, ImageFormat::IntelHex);
+    assert_eq!(segments[0].address, 0x10);
+}
+
+#[test]
+fn intel_hex_errors_are_reported() {
+    // Forced by the extension, even with no record
+    let error = parse("app.HEX", "not a hex image\n", None).unwrap_err();
+    assert!(error.contains("missing ':'"), "{}", error);
+
+    // Forced by the leading ':', even with invalid characters
+    let error = parse("app.img", ":10zz\n", None).unwrap_err();
+    assert!(
+        error.contains("Invalid Intel HEX record at line 1"),
+        "{}",
+        error
+    );
+}
+
+#[test]
+fn binaries_are_not_sniffed() {
+    // A raw binary which happens to start like a HEX record or an ELF
+    for data in [
+        b":\x10\x00\x00".to_vec(),
+        b"  :10".to_vec(),
+        b"\x7fELF\x01".to_vec(),
+    ] {
+        let (format, segments) = parse("app.bin", data.clone(), Some(0x1000)).unwrap();
+
+        assert_eq!(format, ImageFormat::Binary);
+        assert_eq!(segments[0].data, data);
+    }
+}
+
+#[test]
+fn format_overrides_detection() {
+    let hex = [hex_record(0x00, 0x0010, &[1, 2]), hex_record(0x01, 0, &[])].concat();
+
+    let (format, segments) = parse_image(
+        Path::new("app.bin"),
+        hex.into(),
+        None,
+        Some(ImageFormat::IntelHex),
+    )
+    .unwrap();
+    assert_eq!(format, ImageFormat::IntelHex);
+    assert_eq!(segments[0].address, 0x10);
+
+    let (format, segments) = parse_image(
+        Path::new("app.img"),
+        b":not a record".to_vec(),
+        None,
+        Some(ImageFormat::Binary),
+    )
+    .unwrap();
+    assert_eq!(format, ImageFormat::Binary);
+    assert_eq!(segments[0].data, b":not a record");
+}
+
+#[test]
+fn uf2_skips_blocks_not_meant_for_flash() {
+    let uf2 = [
+        uf2_block(0, 0x1800_0000, &[1; 256], 0, 3),
+        uf2_block(0x1, 0x0, b"comment", 1, 3),
+        uf2_block(0, 0x1800_0100, &[2; 256], 2, 3),
+    ]
+    .concat();
+
+    // Detected from its content, with no extension
+    let (format, segments) = parse("firmware", uf2, None).unwrap();
+
+    assert_eq!(format, ImageFormat::Uf2);
+    assert_eq!(segments.len(), 1);
+    assert_eq!(segments[0].address, 0x1800_0000);
+    assert_eq!(segments[0].data.len(), 512);
+    assert_eq!(segments[0].data[256], 2);
+}
+
+#[test]
+fn uf2_is_mapped_from_the_flash_base() {
+    let uf2 = uf2_block(0, 0x1801_0000, &[1; 256], 0, 1);
+    let (format, segments) = parse("app.uf2", uf2, None).unwrap();
+
+    let mapped =
+        map_to_flash(format, segments.clone(), Some(0x1800_0000), Some(0x10_0000)).unwrap();
+    assert_eq!(mapped[0].address, 0x1_0000);
+
+    let error = map_to_flash(format, segments, None, None).unwrap_err();
+    assert!(
+        error.contains("UF2 images carry memory addresses"),
+        "{}",
+        error
+    );
+}
+
+#[test]
+fn uf2_rejects_invalid_blocks() {
+    let mut uf2 = uf2_block(0, 0x1800_0000, &[1; 256], 0, 1);
+    uf2[508] = 0;
+    let error = parse("app.uf2", uf2, None).unwrap_err();
+    assert!(
+        error.contains("Invalid UF2 block 0: bad magic"),
+        "{}",
+        error
+    );
+
+    let mut uf2 = uf2_block(0, 0x1800_0000, &[1; 256], 0, 1);
+    uf2[16..20].copy_from_slice(&477u32.to_le_bytes());
+    let error = parse("app.uf2", uf2, None).unwrap_err();
+    assert!(error.contains("payload too large"), "{}", error);
+
+    let error = parse("app.uf2", vec![0; 100], None).unwrap_err();
+    assert!(error.contains("multiple of 512 bytes"), "{}", error);
+}
+
+#[test]
+fn addressed_formats_are_mapped_from_the_flash_base() {
+    let segments = vec![Segment {
+        address: 0x1801_0000,
+        data: vec![0; 0x1000],
+    }];
+
+    let mapped = map_to_flash(
+        ImageFormat::Elf,
+        segments,
+        Some(0x1800_0000),
+        Some(0x10_0000),
+    )
+    .unwrap();
+
+    assert_eq!(mapped[0].address, 0x1_0000);
+}
+
+#[test]
+fn addressed_formats_require_base_and_size() {
+    for (base, size) in [(None, Some(0x10_0000)), (Some(0x1800_0000), None)] {
+        let segments = vec![Segment {
+            address: 0x1801_0000,
+            data: vec![1],
+        }];
+
+        let error = map_to_flash(ImageFormat::IntelHex, segments, base, size).unwrap_err();
+
+        assert!(error.contains("`base` and `size`"), "{}", error);
+    }
+}
+
+#[test]
+fn segments_out_of_flash_are_rejected() {
+    let segment = |address| Segment {
+        address,
+        data: vec![0; 0x1000],
+    };
+
+    let error = map_to_flash(
+        ImageFormat::Elf,
+        vec![segment(0x0800_0000)],
+        Some(0x1800_0000),
+        Some(0x10_0000),
+    )
+    .unwrap_err();
+    assert!(error.contains("below the flash base"), "{}", error);
+
+    let error = map_to_flash(
+        ImageFormat::Elf,
+        vec![segment(0x180f_f800)],
+        Some(0x1800_0000),
+        Some(0x10_0000),
+    )
+    .unwrap_err();
+    assert!(
+        error.contains("beyond the 1048576 bytes of flash"),
+        "{}",
+        error
+    );
+
+    let error = map_to_flash(
+        ImageFormat::Binary,
+        vec![segment(0x10_0000)],
+        None,
+        Some(0x10_0000),
+    )
+    .unwrap_err();
+    assert!(error.contains("beyond"), "{}", error);
+}
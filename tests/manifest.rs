@@ -0,0 +1,184 @@
+//! Tests of the checks done when loading the manifest
+
+#![cfg(unix)]
+
+mod common;
+
+use common::McpServer;
+
+#[test]
+fn serial_reset_accepts_lines_and_defined_sequences() {
+    for reset in ["dtr", "rts", "boot"] {
+        McpServer::start(&format!(
+            "
+commands: {{}}
+serial:
+  enabled: true
+  reset: {}
+  reset_sequences:
+    boot:
+      - dtr: true
+      - dtr: false
+",
+            reset
+        ));
+    }
+}
+
+#[test]
+fn serial_reset_rejects_misspelled_line() {
+    let error = McpServer::load_error(
+        "
+commands: {}
+serial:
+  enabled: true
+  reset: DTR
+",
+    );
+
+    assert!(error.contains("did you mean \"dtr\""), "{}", error);
+}
+
+#[test]
+fn serial_reset_rejects_undefined_sequence() {
+    let error = McpServer::load_error(
+        "
+commands: {}
+serial:
+  enabled: true
+  reset: bootloader
+  reset_sequences:
+    boot:
+      - dtr: true
+",
+    );
+
+    assert!(
+        error.contains("reset sequence 'bootloader' is not defined"),
+        "{}",
+        error
+    );
+}
+
+#[test]
+fn reset_sequence_cannot_be_named_after_a_line() {
+    let error = McpServer::load_error(
+        "
+commands: {}
+serial:
+  enabled: true
+  reset_sequences:
+    rts:
+      - rts: true
+",
+    );
+
+    assert!(error.contains("named after a reset line"), "{}", error);
+}
+
+#[test]
+fn serial_scenario_cannot_be_named_after_a_tool() {
+    let error = McpServer::load_error(
+        "
+commands:
+  smoke_test:
+    description: Run the smoke test
+    command: echo ok
+serial:
+  enabled: true
+serial_scenarios:
+  serial_read:
+    description: Read the boot log
+    steps:
+      - expect: Booting
+",
+    );
+    assert!(
+        error.contains("Serial scenario 'serial_read' is named after"),
+        "{}",
+        error
+    );
+
+    let error = McpServer::load_error(
+        "
+commands:
+  smoke_test:
+    description: Run the smoke test
+    command: echo ok
+serial:
+  enabled: true
+serial_scenarios:
+  smoke_test:
+    description: Check the shell replies
+    steps:
+      - send: help
+",
+    );
+    assert!(
+        error.contains("Serial scenario 'smoke_test' is named after"),
+        "{}",
+        error
+    );
+}
+
+#[test]
+fn command_cannot_be_named_after_a_tool() {
+    let error = McpServer::load_error(
+        "
+commands:
+  serial_write:
+    description: Write to the device
+    command: echo ok
+serial:
+  enabled: true
+",
+    );
+
+    assert!(
+        error.contains("Command 'serial_write' is named after a built-in tool"),
+        "{}",
+        error
+    );
+}
+
+#[test]
+fn serial_scenario_steps_are_checked() {
+    for (step, expected) in [
+        (
+            "- send: help\n        expect: uart",
+            "exactly one of `reset`, `send`, `expect`, `delay` and `assert`",
+        ),
+        ("- expect: \"(unclosed\"", "Invalid regular expression"),
+        ("- assert: \"{{version}}\"", "`assert` requires `matches`"),
+        (
+            "- send: help\n        matches: ok",
+            "`matches` only applies to `assert`",
+        ),
+        (
+            "- reset: bootloader",
+            "reset sequence 'bootloader' is not defined",
+        ),
+    ] {
+        let error = McpServer::load_error(&format!(
+            "
+commands: {{}}
+serial:
+  enabled: true
+serial_scenarios:
+  smoke_test:
+    description: Check the shell replies
+    steps:
+      - send: kernel version
+      {}
+",
+            step
+        ));
+
+        assert!(
+            error.contains("Step 2 of serial scenario 'smoke_test' is invalid"),
+            "{}",
+            error
+        );
+        assert!(error.contains(expected), "{}", error);
+    }
+}
@@ -0,0 +1,506 @@
+//! End-to-end tests of the serial tools against virtual devices
+
+#![cfg(unix)]
+
+mod common;
+
+use std::{
+    thread::{sleep, spawn},
+    time::Duration,
+};
+
+use serde_json::json;
+
+use common::{DeviceScript, McpServer, VirtualDevice};
+
+const MANIFEST: &str = "
+commands: {}
+serial:
+  enabled: true
+  reset: dtr
+  reset_interval: 50
+";
+
+#[test]
+fn serial_read_resets_into_boot_log() {
+    let device = VirtualDevice::rfc2217(DeviceScript::default());
+    let mut server = McpServer::start(MANIFEST);
+
+    let result = server
+        .call(
+            "serial_read",
+            json!({ "port": device.port, "timeout": 3000, "until": "uart:~\\$" }),
+        )
+        .unwrap();
+
+    assert!(!result.is_error, "{}", result.text);
+    assert!(result.text.contains("Booting Zephyr OS"), "{}", result.text);
+    assert!(
+        result.text.contains("<inf> main: Hello World!"),
+        "{}",
+        result.text
+    );
+    assert_eq!(device.resets(), 1);
+}
+
+#[test]
+fn serial_read_without_reset() {
+    let device = VirtualDevice::rfc2217(DeviceScript::default());
+    let mut server = McpServer::start(MANIFEST);
+
+    let result = server
+        .call(
+            "serial_read",
+            json!({ "port": device.port, "timeout": 500, "reset": false }),
+        )
+        .unwrap();
+
+    assert!(
+        !result.text.contains("Booting Zephyr OS"),
+        "{}",
+        result.text
+    );
+    assert_eq!(device.resets(), 0);
+}
+
+#[test]
+fn serial_reset_pulses_dtr() {
+    let device = VirtualDevice::rfc2217(DeviceScript::default());
+    let mut server = McpServer::start(MANIFEST);
+
+    let result = server
+        .call("serial_reset", json!({ "port": device.port }))
+        .unwrap();
+
+    assert!(!result.is_error, "{}", result.text);
+    assert_eq!(device.resets(), 1);
+}
+
+// Linux ptys have no modem lines: TIOCMBIS and TIOCMBIC fail with ENOTTY, so
+// DTR pulses are tested over RFC 2217 above, and a pty can only check the
+// failure is reported.
+#[test]
+fn serial_reset_reports_missing_modem_lines() {
+    let device = VirtualDevice::pty(DeviceScript::default());
+    let mut server = McpServer::start(MANIFEST);
+
+    let error = server
+        .call("serial_reset", json!({ "port": device.port }))
+        .err()
+        .unwrap();
+
+    assert!(error.contains("Failed to reset device"), "{}", error);
+}
+
+#[test]
+fn serial_write_reaches_device() {
+    let device = VirtualDevice::pty(DeviceScript::default());
+    let mut server = McpServer::start(MANIFEST);
+
+    let result = server
+        .call(
+            "serial_write",
+            json!({ "port": device.port, "data": "help" }),
+        )
+        .unwrap();
+    assert!(!result.is_error, "{}", result.text);
+
+    sleep(Duration::from_millis(200));
+    assert_eq!(device.received(), b"help\n");
+}
+
+#[test]
+fn serial_send_expect_matches_shell_reply() {
+    let device = VirtualDevice::pty(DeviceScript::default());
+    let mut server = McpServer::start(MANIFEST);
+
+    let result = server
+        .call(
+            "serial_send_expect",
+            json!({
+                "port": device.port,
+                "data": "kernel version",
+                "expect": "Zephyr version (\\S+)",
+                "timeout": 3000,
+            }),
+        )
+        .unwrap();
+
+    assert!(!result.is_error, "{}", result.text);
+    assert!(result.text.contains("3.5.0"), "{}", result.text);
+}
+
+#[test]
+fn serial_send_expect_times_out() {
+    let device = VirtualDevice::pty(DeviceScript::default());
+    let mut server = McpServer::start(MANIFEST);
+
+    let result = server
+        .call(
+            "serial_send_expect",
+            json!({
+                "port": device.port,
+                "data": "reboot",
+                "expect": "Booting",
+                "timeout": 500,
+            }),
+        )
+        .unwrap();
+
+    assert!(result.is_error, "{}", result.text);
+    assert!(
+        result.text.contains("No match for `Booting` within 500ms"),
+        "{}",
+        result.text
+    );
+}
+
+#[test]
+fn serial_read_receives_device_output() {
+    let device = VirtualDevice::pty(DeviceScript::default());
+    let mut server = McpServer::start(MANIFEST);
+
+    let port = device.port.clone();
+    let emitter = spawn(move || {
+        sleep(Duration::from_millis(300));
+        device.emit("E: sensor timeout\r\nI: retrying\r\n");
+        sleep(Duration::from_millis(1000));
+    });
+
+    let result = server
+        .call(
+            "serial_read",
+            json!({
+                "port": port,
+                "timeout": 3000,
+                "reset": false,
+                "until": "retrying",
+                "summary": true,
+            }),
+        )
+        .unwrap();
+    emitter.join().unwrap();
+
+    assert!(!result.is_error, "{}", result.text);
+    assert!(result.text.contains("E: sensor timeout"), "{}", result.text);
+    assert!(result.text.contains("1 error"), "{}", result.text);
+}
+
+#[test]
+fn serial_read_parses_colored_logs_kept_as_is() {
+    let device = VirtualDevice::pty(DeviceScript::default());
+    let mut server = McpServer::start(MANIFEST);
+
+    let port = device.port.clone();
+    let emitter = spawn(move || {
+        sleep(Duration::from_millis(300));
+        device.emit("\x1b[1;31m[00:00:01.000,000] <err> sensor: timeout\x1b[0m\r\nI: done\r\n");
+        sleep(Duration::from_millis(1000));
+    });
+
+    let result = server
+        .call(
+            "serial_read",
+            json!({
+                "port": port,
+                "timeout": 3000,
+                "reset": false,
+                "until": "done",
+                "summary": true,
+                "strip_ansi": false,
+            }),
+        )
+        .unwrap();
+    emitter.join().unwrap();
+
+    assert!(!result.is_error, "{}", result.text);
+    assert!(result.text.contains("1 error"), "{}", result.text);
+    assert!(result.text.contains("sensor: timeout"), "{}", result.text);
+    assert!(result.text.contains("\x1b[1;31m"), "{}", result.text);
+}
+
+#[test]
+fn serial_scenario_runs_against_device() {
+    let device = VirtualDevice::rfc2217(DeviceScript::default());
+    let mut server = McpServer::start(&format!(
+        "{}
+serial_scenarios:
+  smoke_test:
+    description: Boot and check the kernel version
+    port: {}
+    steps:
+      - reset: true
+      - expect: \"uart:~\\\\$ \"
+      - send: kernel version
+      - expect: \"Zephyr version (?P<version>[\\\\d.]+)\"
+      - assert: \"{{{{version}}}}\"
+        matches: \"^3\\\\.\"
+",
+        MANIFEST, device.port
+    ));
+
+    let result = server.call("smoke_test", json!({})).unwrap();
+
+    assert!(!result.is_error, "{}", result.text);
+    assert!(result.text.contains("version: `3.5.0`"), "{}", result.text);
+    assert_eq!(device.resets(), 1);
+}
+
+#[test]
+fn serial_read_reports_recorded_files() {
+    let device = VirtualDevice::pty(DeviceScript::default());
+    let mut server = McpServer::start(MANIFEST);
+
+    let port = device.port.clone();
+    let emitter = spawn(move || {
+        sleep(Duration::from_millis(300));
+        device.emit("I: recorded\r\n");
+        sleep(Duration::from_millis(1000));
+    });
+
+    let result = server
+        .call(
+            "serial_read",
+            json!({
+                "port": port,
+                "timeout": 3000,
+                "reset": false,
+                "until": "recorded",
+                "record": true,
+            }),
+        )
+        .unwrap();
+    emitter.join().unwrap();
+
+    assert!(!result.is_error, "{}", result.text);
+    let files = result
+        .text
+        .lines()
+        .filter_map(|line| line.strip_prefix("- "))
+        .collect::<Vec<_>>();
+    assert_eq!(files.len(), 2, "{}", result.text);
+    assert!(files[0].ends_with(".log"), "{}", result.text);
+    assert!(files[1].ends_with(".raw"), "{}", result.text);
+    assert_eq!(std::fs::read(files[1]).unwrap(), b"I: recorded\r\n");
+}
+
+#[test]
+fn serial_capture_merges_ports_in_time_order() {
+    let first = VirtualDevice::pty(DeviceScript::default());
+    let second = VirtualDevice::pty(DeviceScript::default());
+    let mut server = McpServer::start(MANIFEST);
+
+    let ports = (first.port.clone(), second.port.clone());
+    let emitter = spawn(move || {
+        sleep(Duration::from_millis(300));
+        first.emit("net: connecting\r\n");
+        sleep(Duration::from_millis(300));
+        second.emit("modem: attached\r\n");
+        sleep(Duration::from_millis(300));
+        first.emit("net: connected\r\n");
+        sleep(Duration::from_millis(1000));
+    });
+
+    let result = server
+        .call(
+            "serial_capture",
+            json!({
+                "ports": [{ "port": ports.0 }, { "port": ports.1 }],
+                "timeout": 1500,
+            }),
+        )
+        .unwrap();
+    emitter.join().unwrap();
+
+    assert!(!result.is_error, "{}", result.text);
+    let lines = result
+        .text
+        .lines()
+        .filter(|line| line.starts_with('['))
+        .collect::<Vec<_>>();
+    assert_eq!(lines.len(), 3, "{}", result.text);
+    assert!(
+        lines[0].ends_with(&format!("{}: net: connecting", ports.0)),
+        "{}",
+        result.text
+    );
+    assert!(
+        lines[1].ends_with(&format!("{}: modem: attached", ports.1)),
+        "{}",
+        result.text
+    );
+    assert!(
+        lines[2].ends_with(&format!("{}: net: connected", ports.0)),
+        "{}",
+        result.text
+    );
+}
+
+#[test]
+fn serial_capture_resets_each_port_with_its_method() {
+    let first = VirtualDevice::rfc2217(DeviceScript::default());
+    let second = VirtualDevice::rfc2217(DeviceScript::default());
+    let mut server = McpServer::start(MANIFEST);
+
+    let result = server
+        .call(
+            "serial_capture",
+            json!({
+                "ports": [
+                    { "port": first.port, "reset": "rts" },
+                    { "port": second.port, "reset": false },
+                ],
+                "timeout": 1000,
+            }),
+        )
+        .unwrap();
+
+    assert!(!result.is_error, "{}", result.text);
+    assert!(
+        result
+            .text
+            .contains(&format!("{}: *** Booting Zephyr OS", first.port)),
+        "{}",
+        result.text
+    );
+    assert_eq!((first.resets(), second.resets()), (1, 0));
+}
+
+#[test]
+fn serial_capture_rejects_undefined_reset_sequence() {
+    let device = VirtualDevice::rfc2217(DeviceScript::default());
+    let mut server = McpServer::start(MANIFEST);
+
+    let error = server
+        .call(
+            "serial_capture",
+            json!({
+                "ports": [{ "port": device.port, "reset": "bootloader" }],
+                "timeout": 1000,
+            }),
+        )
+        .err()
+        .unwrap();
+
+    assert!(error.contains("'bootloader' is not defined"), "{}", error);
+    assert_eq!(device.resets(), 0);
+}
+
+#[test]
+fn serial_monitor_is_found_by_any_name_of_the_port() {
+    let device = VirtualDevice::pty(DeviceScript::default());
+    let mut server = McpServer::start(MANIFEST);
+
+    let link = std::env::temp_dir().join(format!("commands-mcp-test-{}-link", std::process::id()));
+    let _ = std::fs::remove_file(&link);
+    std::os::unix::fs::symlink(&device.port, &link).unwrap();
+    let link = link.to_string_lossy().to_string();
+
+    let result = server
+        .call(
+            "serial_monitor_start",
+            json!({ "port": link, "reset": false }),
+        )
+        .unwrap();
+    assert!(!result.is_error, "{}", result.text);
+
+    device.emit("I: monitored\r\n");
+    sleep(Duration::from_millis(500));
+
+    let result = server
+        .call("serial_monitor_read", json!({ "port": device.port }))
+        .unwrap();
+    assert!(!result.is_error, "{}", result.text);
+    assert!(result.text.contains("I: monitored"), "{}", result.text);
+
+    let result = server
+        .call("serial_monitor_stop", json!({ "port": device.port }))
+        .unwrap();
+    assert!(!result.is_error, "{}", result.text);
+
+    // The port is released as soon as the monitor is stopped
+    let result = server
+        .call("serial_write", json!({ "port": link, "data": "help" }))
+        .unwrap();
+    assert!(!result.is_error, "{}", result.text);
+
+    std::fs::remove_file(&link).unwrap();
+}
+
+#[test]
+fn namespaced_tools_route_to_their_handlers() {
+    let device = VirtualDevice::rfc2217(DeviceScript::default());
+    let mut server = McpServer::start(&format!(
+        "
+namespace: bench
+commands:
+  greet:
+    description: Say hello
+    command: echo hello from the command
+serial:
+  enabled: true
+  reset: dtr
+  reset_interval: 50
+serial_scenarios:
+  smoke_test:
+    description: Boot the device
+    port: {}
+    steps:
+      - reset: true
+      - expect: \"uart:~\\\\$ \"
+",
+        device.port
+    ));
+    let project = server
+        .dir()
+        .file_name()
+        .unwrap()
+        .to_string_lossy()
+        .to_string();
+
+    let tools = server.list_tools();
+    for name in ["bench_greet", "bench_serial_write", "bench_smoke_test"] {
+        let (_, description) = tools
+            .iter()
+            .find(|(tool, _)| tool == name)
+            .unwrap_or_else(|| panic!("{} not listed in {:?}", name, tools));
+        assert!(
+            description.starts_with(&format!("[{}] ", project)),
+            "{}",
+            description
+        );
+    }
+    assert!(
+        tools.iter().all(|(name, _)| name.starts_with("bench_")),
+        "{:?}",
+        tools
+    );
+
+    let result = server.call("bench_greet", json!({})).unwrap();
+    assert!(!result.is_error, "{}", result.text);
+    assert!(
+        result.text.contains("hello from the command"),
+        "{}",
+        result.text
+    );
+
+    let result = server
+        .call(
+            "bench_serial_write",
+            json!({ "port": device.port, "data": "kernel version" }),
+        )
+        .unwrap();
+    assert!(!result.is_error, "{}", result.text);
+
+    let result = server.call("bench_smoke_test", json!({})).unwrap();
+    assert!(!result.is_error, "{}", result.text);
+    assert_eq!(device.resets(), 1);
+    assert!(
+        String::from_utf8_lossy(&device.received()).contains("kernel version"),
+        "{:?}",
+        device.received()
+    );
+
+    // The names without the namespace aren't routed
+    assert!(server.call("greet", json!({})).is_err());
+}
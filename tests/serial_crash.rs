@@ -0,0 +1,142 @@
+//! Tests of the crash dump detection, and of the symbolization against a
+//! sample ELF built from `fixtures/crash.c`
+
+#[allow(unused)]
+#[path = "../src/serial_crash.rs"]
+mod serial_crash;
+
+use std::path::Path;
+
+use serial_crash::{Symbolizer, analyze_crashes, find_crashes};
+
+// Addresses in the sample ELF
+const SENSOR_READ: u64 = 0x401000;
+const SENSOR_READ_FAULT: u64 = 0x401009;
+const MAIN_RETURN: u64 = 0x40101d;
+const SENSOR_STATE: u64 = 0x402000;
+
+const ZEPHYR_FAULT: &[&str] = &[
+    "[00:00:01.230,000] <inf> main: Reading sensor",
+    "[00:00:01.234,000] <err> os: ***** MPU FAULT *****",
+    "[00:00:01.234,000] <err> os:   Data Access Violation",
+    "[00:00:01.234,000] <err> os:   MMFAR Address: 0x0",
+    "[00:00:01.234,000] <err> os: r0/a1:  0x00402000  r1/a2:  0x00000000  r2/a3:  0x00000000",
+    "[00:00:01.234,000] <err> os: lr:  0x0040101d",
+    "[00:00:01.234,000] <err> os: Faulting instruction address (r15/pc): 0x00401009",
+    "[00:00:01.234,000] <err> os: >>> ZEPHYR FATAL ERROR 19: Unknown error on CPU 0",
+    "[00:00:01.234,000] <err> os: Current thread: 0x00402000 (unknown)",
+    "[00:00:01.240,000] <err> os: Halting system",
+    "uart:~$ ",
+];
+
+const ESP_IDF_PANIC: &[&str] = &[
+    "I (312) main_task: Calling app_main()",
+    "Guru Meditation Error: Core  0 panic'ed (LoadProhibited). Exception was unhandled.",
+    "",
+    "Core  0 register dump:",
+    "PC      : 0x400d1234  PS      : 0x00060030  A0      : 0x800d5678",
+    "",
+    "Backtrace: 0x400d1231:0x3ffb1f40 0x400d5675:0x3ffb1f60",
+    "",
+    "ELF file SHA256: 0123456789abcdef",
+    "",
+    "Rebooting...",
+    "ets Jun  8 2016 00:22:57",
+];
+
+fn fixture(name: &str) -> Symbolizer {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/fixtures")
+        .join(name);
+    Symbolizer::open(&path).unwrap()
+}
+
+#[test]
+fn zephyr_fault_is_detected_until_halt() {
+    let crashes = find_crashes(ZEPHYR_FAULT);
+
+    assert_eq!(crashes.len(), 1);
+    assert_eq!((crashes[0].start, crashes[0].end), (1, 10));
+    assert_eq!(
+        crashes[0].addresses,
+        [SENSOR_STATE, 0, MAIN_RETURN, SENSOR_READ_FAULT]
+    );
+}
+
+#[test]
+fn esp_idf_panic_and_backtrace_are_one_crash() {
+    let crashes = find_crashes(ESP_IDF_PANIC);
+
+    assert_eq!(crashes.len(), 1);
+    assert_eq!((crashes[0].start, crashes[0].end), (1, 9));
+    assert_eq!(
+        crashes[0].addresses,
+        [
+            0x400d1234, 0x00060030, 0x800d5678, 0x400d1231, 0x3ffb1f40, 0x400d5675, 0x3ffb1f60
+        ]
+    );
+}
+
+#[test]
+fn normal_output_has_no_crash() {
+    let lines = [
+        "*** Booting Zephyr OS build v3.5.0 ***",
+        "[00:00:00.010,000] <wrn> sensor: Retrying after timeout 0x1000",
+        "uart:~$ ",
+    ];
+
+    assert!(find_crashes(&lines).is_empty());
+}
+
+#[test]
+fn addresses_resolve_to_function_and_line() {
+    let symbolizer = fixture("crash.elf");
+
+    let frames = symbolizer.symbolize(SENSOR_READ_FAULT).unwrap();
+
+    assert_eq!(frames[0].function, "sensor_read");
+    let location = frames[0].location.as_deref().unwrap();
+    assert!(location.ends_with("crash.c:15"), "{}", location);
+}
+
+#[test]
+fn addresses_resolve_to_functions_without_debug_info() {
+    let symbolizer = fixture("crash-nodebug.elf");
+
+    let frames = symbolizer.symbolize(SENSOR_READ).unwrap();
+    assert_eq!(frames[0].function, "sensor_read");
+    assert!(frames[0].location.is_none());
+
+    let frames = symbolizer.symbolize(MAIN_RETURN).unwrap();
+    assert_eq!(frames[0].function, "main");
+}
+
+#[test]
+fn data_addresses_are_not_resolved() {
+    for elf in ["crash.elf", "crash-nodebug.elf"] {
+        let symbolizer = fixture(elf);
+
+        assert!(symbolizer.symbolize(SENSOR_STATE).is_none(), "{}", elf);
+        assert!(symbolizer.symbolize(0).is_none(), "{}", elf);
+    }
+}
+
+#[test]
+fn analysis_lists_code_addresses_only() {
+    let symbolizer = fixture("crash-nodebug.elf");
+
+    let analysis = analyze_crashes(&symbolizer, ZEPHYR_FAULT).unwrap();
+
+    assert!(
+        analysis.contains("Crash detected at line 2"),
+        "{}",
+        analysis
+    );
+    assert!(
+        analysis.contains("| 0x00401009 | sensor_read |"),
+        "{}",
+        analysis
+    );
+    assert!(analysis.contains("| 0x0040101d | main |"), "{}", analysis);
+    assert!(!analysis.contains("0x00402000"), "{}", analysis);
+}
@@ -0,0 +1,239 @@
+//! Tests of the decoding of Zephyr's dictionary based logs, with messages
+//! built like a little endian 32-bit target sends them
+
+#[allow(unused)]
+#[path = "../src/serial_dictionary.rs"]
+mod serial_dictionary;
+
+use std::{
+    env, fs,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use base64::{Engine, prelude::BASE64_STANDARD};
+use serde_json::json;
+
+use serial_dictionary::{DictionaryDecoder, LogDictionary};
+
+/// Address of the strings of the firmware
+const RODATA: u32 = 0x1000;
+
+/// Strings of the firmware, laid out from [`RODATA`]
+const STRINGS: [&str; 4] = [
+    "Sensor %s reads %d\n",
+    "bme280",
+    "Uptime %lld ms, %.2f V\n",
+    "Boot complete\n",
+];
+
+/// Source ID of the `main` log module
+const MAIN: u32 = 1;
+
+/// Level of info messages
+const INF: u8 = 3;
+
+/// Address of one of [`STRINGS`]
+fn string(index: usize) -> u32 {
+    RODATA
+        + STRINGS[..index]
+            .iter()
+            .map(|string| string.len() as u32 + 1)
+            .sum::<u32>()
+}
+
+/// A decoder with the dictionary of [`STRINGS`] and the given kconfigs
+fn decoder(kconfigs: serde_json::Value) -> DictionaryDecoder {
+    static NEXT_FILE: AtomicUsize = AtomicUsize::new(0);
+
+    let mut rodata = Vec::new();
+    for string in STRINGS {
+        rodata.extend_from_slice(string.as_bytes());
+        rodata.push(0);
+    }
+
+    let path = env::temp_dir().join(format!(
+        "commands-mcp-dictionary-{}-{}.json",
+        std::process::id(),
+        NEXT_FILE.fetch_add(1, Ordering::Relaxed)
+    ));
+    let database = json!({
+        "target": { "bits": 32, "little_endianness": true },
+        "kconfigs": kconfigs,
+        "log_subsys": { "log_instances": { MAIN.to_string(): { "name": "main" } } },
+        "sections": {
+            "rodata": { "start": RODATA, "data_b64": BASE64_STANDARD.encode(&rodata) },
+        },
+    });
+    fs::write(&path, database.to_string()).unwrap();
+
+    let dictionary = LogDictionary::open(&path).unwrap();
+    fs::remove_file(&path).unwrap();
+    DictionaryDecoder::new(dictionary)
+}
+
+/// A normal message of the format string with the arguments (32-bit aligned)
+/// and appended strings, each prefixed by the index of its argument
+fn message(timestamp: u32, format: u32, args: &[u8], appended: &[(u8, &str)]) -> Vec<u8> {
+    let mut package = vec![(8 + args.len()) as u8 / 4, appended.len() as u8, 0, 0];
+    package.extend_from_slice(&format.to_le_bytes());
+    package.extend_from_slice(args);
+    for (index, string) in appended {
+        package.push(*index);
+        package.extend_from_slice(string.as_bytes());
+        package.push(0);
+    }
+
+    let mut message = vec![0, INF << 4];
+    message.extend_from_slice(&(package.len() as u16).to_le_bytes());
+    message.extend_from_slice(&0u16.to_le_bytes());
+    message.extend_from_slice(&MAIN.to_le_bytes());
+    message.extend_from_slice(&timestamp.to_le_bytes());
+    message.extend_from_slice(&package);
+    message
+}
+
+/// `Sensor %s reads %d` with the sensor name pointer and the reading
+fn sensor_reading(name: u32, reading: i32) -> Vec<u8> {
+    [name.to_le_bytes(), reading.to_le_bytes()].concat()
+}
+
+#[test]
+fn decodes_strings_from_the_dictionary() {
+    let mut decoder = decoder(json!({}));
+
+    let lines = decoder.feed(&message(
+        1234,
+        string(0),
+        &sensor_reading(string(1), -5),
+        &[],
+    ));
+
+    assert_eq!(lines, ["[00001234] <inf> main: Sensor bme280 reads -5"]);
+}
+
+#[test]
+fn decodes_appended_strings() {
+    let mut decoder = decoder(json!({}));
+
+    // The string argument is the third 32-bit word of the package
+    let lines = decoder.feed(&message(
+        1,
+        string(0),
+        &sensor_reading(0, 7),
+        &[(2, "lis3dh")],
+    ));
+
+    assert_eq!(lines, ["[00000001] <inf> main: Sensor lis3dh reads 7"]);
+}
+
+#[test]
+fn decodes_64_bit_and_floating_point_arguments() {
+    let mut decoder = decoder(json!({}));
+
+    let args = [12_345_678_901i64.to_le_bytes(), 3.3f64.to_le_bytes()].concat();
+    let lines = decoder.feed(&message(42, string(2), &args, &[]));
+
+    assert_eq!(
+        lines,
+        ["[00000042] <inf> main: Uptime 12345678901 ms, 3.30 V"]
+    );
+}
+
+#[test]
+fn reports_dropped_messages() {
+    let mut decoder = decoder(json!({}));
+
+    let lines = decoder.feed(&[1, 3, 0]);
+
+    assert_eq!(lines, ["--- 3 messages dropped ---"]);
+}
+
+#[test]
+fn keeps_text_outside_of_the_logs() {
+    let mut decoder = decoder(json!({}));
+
+    let mut data = b"*** Booting Zephyr OS ***\r\n".to_vec();
+    data.extend(message(5, string(3), &[], &[]));
+    let lines = decoder.feed(&data);
+
+    assert_eq!(
+        lines,
+        [
+            "*** Booting Zephyr OS ***",
+            "[00000005] <inf> main: Boot complete"
+        ]
+    );
+}
+
+#[test]
+fn resyncs_after_garbage() {
+    let mut decoder = decoder(json!({}));
+
+    let mut data = vec![0xff, 0xfe, 0x80];
+    data.extend(message(5, string(3), &[], &[]));
+    let lines = decoder.feed(&data);
+
+    assert_eq!(
+        lines,
+        [
+            "<decoding error: skipped 3 bytes not forming a valid message>",
+            "[00000005] <inf> main: Boot complete"
+        ]
+    );
+}
+
+#[test]
+fn decodes_messages_split_across_feeds() {
+    let mut decoder = decoder(json!({}));
+
+    let data = message(1234, string(0), &sensor_reading(string(1), 21), &[]);
+    let (last, bytes) = data.split_last().unwrap();
+    for byte in bytes {
+        assert!(decoder.feed(&[*byte]).is_empty());
+    }
+
+    assert_eq!(
+        decoder.feed(&[*last]),
+        ["[00001234] <inf> main: Sensor bme280 reads 21"]
+    );
+    assert!(decoder.finish().is_empty());
+}
+
+#[test]
+fn reports_incomplete_message_at_the_end() {
+    let mut decoder = decoder(json!({}));
+
+    let data = message(1234, string(0), &sensor_reading(string(1), 21), &[]);
+    assert!(decoder.feed(&data[..data.len() - 2]).is_empty());
+
+    let lines = decoder.finish();
+    assert_eq!(lines.len(), 1);
+    assert!(lines[0].starts_with("<decoding error"), "{:?}", lines);
+}
+
+#[test]
+fn decodes_hex_output() {
+    let mut decoder = decoder(json!({ "CONFIG_LOG_BACKEND_UART_OUTPUT_DICTIONARY_HEX": "y" }));
+
+    let hex = [
+        message(5, string(3), &[], &[]),
+        message(1234, string(0), &sensor_reading(string(1), -5), &[]),
+    ]
+    .concat()
+    .iter()
+    .map(|byte| format!("{:02x}", byte))
+    .collect::<String>();
+
+    // Split within a byte, with line breaks between the chunks
+    let (first, second) = hex.split_at(11);
+    let mut lines = decoder.feed(format!("{}\r\n", first).as_bytes());
+    lines.extend(decoder.feed(second.as_bytes()));
+
+    assert_eq!(
+        lines,
+        [
+            "[00000005] <inf> main: Boot complete",
+            "[00001234] <inf> main: Sensor bme280 reads -5"
+        ]
+    );
+}
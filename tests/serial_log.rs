@@ -0,0 +1,117 @@
+//! Tests of the parsing of log lines in the formats of common firmwares
+
+#[allow(unused)]
+#[path = "../src/serial_log.rs"]
+mod serial_log;
+
+use serial_log::{LogLevel, LogRecord};
+
+/// The level, module, timestamp and message parsed from the line
+fn parse(line: &str) -> (Option<LogLevel>, Option<&str>, Option<&str>, &str) {
+    let record = LogRecord::parse(line);
+    (
+        record.level,
+        record.module,
+        record.timestamp,
+        record.message,
+    )
+}
+
+#[test]
+fn zephyr_logs() {
+    assert_eq!(
+        parse("[00:00:01.234,000] <err> sensor: read failed: -5"),
+        (
+            Some(LogLevel::Error),
+            Some("sensor"),
+            Some("00:00:01.234,000"),
+            "read failed: -5"
+        )
+    );
+    assert_eq!(
+        parse("<wrn> net/ip: no route"),
+        (Some(LogLevel::Warning), Some("net/ip"), None, "no route")
+    );
+    // As decoded from dictionary based logs, with the raw timestamp
+    assert_eq!(
+        parse("[00012345] <inf> main: Hello World!"),
+        (
+            Some(LogLevel::Info),
+            Some("main"),
+            Some("00012345"),
+            "Hello World!"
+        )
+    );
+    // Without module, e.g. printk through the log subsystem
+    assert_eq!(
+        parse("<dbg> state changed"),
+        (Some(LogLevel::Debug), None, None, "state changed")
+    );
+}
+
+#[test]
+fn esp_idf_logs() {
+    assert_eq!(
+        parse("E (1234) wifi: connection lost"),
+        (
+            Some(LogLevel::Error),
+            Some("wifi"),
+            Some("1234"),
+            "connection lost"
+        )
+    );
+    assert_eq!(
+        parse("\x1b[0;33mW (56) boot: no factory partition\x1b[0m"),
+        (
+            Some(LogLevel::Warning),
+            Some("boot"),
+            Some("56"),
+            "no factory partition"
+        )
+    );
+    assert_eq!(parse("V (7) heap: init").0, Some(LogLevel::Verbose));
+}
+
+#[test]
+fn minimal_logs() {
+    assert_eq!(
+        parse("E: sensor timeout"),
+        (Some(LogLevel::Error), None, None, "sensor timeout")
+    );
+    assert_eq!(
+        parse("I: retrying"),
+        (Some(LogLevel::Info), None, None, "retrying")
+    );
+    // Not a level
+    assert_eq!(parse("X: unknown").0, None);
+}
+
+#[test]
+fn plain_logs() {
+    assert_eq!(
+        parse("[ERROR] flash write failed"),
+        (Some(LogLevel::Error), None, None, "flash write failed")
+    );
+    assert_eq!(
+        parse("  warn: low battery"),
+        (Some(LogLevel::Warning), None, None, "low battery")
+    );
+    assert_eq!(
+        parse("[Debug]: tick"),
+        (Some(LogLevel::Debug), None, None, "tick")
+    );
+    assert_eq!(parse("FATAL: panic").0, Some(LogLevel::Error));
+    assert_eq!(parse("TRACE: enter").0, Some(LogLevel::Verbose));
+}
+
+#[test]
+fn other_lines_are_kept_whole() {
+    for line in [
+        "*** Booting Zephyr OS build v3.5.0 ***",
+        "uart:~$ kernel version",
+        "information: not a level",
+        "",
+    ] {
+        assert_eq!(parse(line), (None, None, None, line));
+    }
+}
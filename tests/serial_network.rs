@@ -0,0 +1,168 @@
+//! Tests of the network serial ports against virtual devices served over
+//! raw TCP and RFC 2217
+
+#![cfg(unix)]
+
+mod common;
+
+use std::{thread::sleep, time::Duration};
+
+use serde_json::json;
+
+use common::{
+    COM_PORT_OPTION, DO, DONT, DeviceScript, ECHO, McpServer, SET_BAUDRATE, TERMINAL_TYPE,
+    VirtualDevice, WILL, WONT,
+};
+
+const MANIFEST: &str = "
+commands: {}
+serial:
+  enabled: true
+";
+
+// Telnet options requested by the client
+const BINARY: u8 = 0;
+const SUPPRESS_GO_AHEAD: u8 = 3;
+
+#[test]
+fn rfc2217_negotiates_options_and_refuses_others() {
+    let device = VirtualDevice::rfc2217(DeviceScript::default());
+    let mut server = McpServer::start(MANIFEST);
+
+    let result = server
+        .call(
+            "serial_send_expect",
+            json!({
+                "port": device.port,
+                "data": "kernel version",
+                "expect": "Zephyr version",
+                "timeout": 3000,
+            }),
+        )
+        .unwrap();
+    assert!(!result.is_error, "{}", result.text);
+
+    sleep(Duration::from_millis(200));
+    let negotiations = device.negotiations();
+    for expected in [
+        (WILL, COM_PORT_OPTION),
+        (WILL, BINARY),
+        (DO, BINARY),
+        (WILL, SUPPRESS_GO_AHEAD),
+        (DO, SUPPRESS_GO_AHEAD),
+        // Offered by the server
+        (WONT, TERMINAL_TYPE),
+        (DONT, ECHO),
+    ] {
+        assert!(
+            negotiations.contains(&expected),
+            "{:?} not in {:?}",
+            expected,
+            negotiations
+        );
+    }
+}
+
+#[test]
+fn rfc2217_sets_line_parameters() {
+    let device = VirtualDevice::rfc2217(DeviceScript::default());
+    let mut server = McpServer::start(MANIFEST);
+
+    let result = server
+        .call(
+            "serial_write",
+            json!({ "port": device.port, "data": "help", "baudrate": 921600 }),
+        )
+        .unwrap();
+    assert!(!result.is_error, "{}", result.text);
+
+    sleep(Duration::from_millis(200));
+    let commands = device.com_port_commands();
+    assert!(
+        commands.contains(&vec![SET_BAUDRATE, 0x00, 0x0e, 0x10, 0x00]),
+        "{:?}",
+        commands
+    );
+}
+
+#[test]
+fn rfc2217_escapes_iac() {
+    let device = VirtualDevice::rfc2217(DeviceScript::default());
+    let mut server = McpServer::start(MANIFEST);
+
+    // 0x0000ffff, with IAC in the value of the command
+    let result = server
+        .call(
+            "serial_write",
+            json!({
+                "port": device.port,
+                "data": "ff 41 ff",
+                "hex": true,
+                "baudrate": 65535,
+            }),
+        )
+        .unwrap();
+    assert!(!result.is_error, "{}", result.text);
+
+    sleep(Duration::from_millis(200));
+    assert_eq!(device.received(), [0xff, 0x41, 0xff]);
+    assert!(
+        device
+            .com_port_commands()
+            .contains(&vec![SET_BAUDRATE, 0x00, 0x00, 0xff, 0xff]),
+        "{:?}",
+        device.com_port_commands()
+    );
+}
+
+#[test]
+fn tcp_passes_data_as_is() {
+    let device = VirtualDevice::tcp(DeviceScript::default());
+    let mut server = McpServer::start(MANIFEST);
+
+    let result = server
+        .call(
+            "serial_send_expect",
+            json!({
+                "port": device.port,
+                "data": "kernel version",
+                "expect": "Zephyr version (\\S+)",
+                "timeout": 3000,
+            }),
+        )
+        .unwrap();
+    assert!(!result.is_error, "{}", result.text);
+    assert!(result.text.contains("3.5.0"), "{}", result.text);
+
+    let result = server
+        .call(
+            "serial_write",
+            json!({ "port": device.port, "data": "ff 41", "hex": true }),
+        )
+        .unwrap();
+    assert!(!result.is_error, "{}", result.text);
+
+    sleep(Duration::from_millis(200));
+    assert!(device.received().ends_with(&[0xff, 0x41]));
+    assert!(device.negotiations().is_empty());
+}
+
+#[test]
+fn tcp_cannot_reset() {
+    let device = VirtualDevice::tcp(DeviceScript::default());
+    let mut server = McpServer::start(
+        "
+commands: {}
+serial:
+  enabled: true
+  reset: dtr
+",
+    );
+
+    let error = server
+        .call("serial_reset", json!({ "port": device.port }))
+        .err()
+        .unwrap();
+
+    assert!(error.contains("use rfc2217://"), "{}", error);
+}
@@ -0,0 +1,78 @@
+//! Tests of the rotation of the files recorded from serial ports
+
+#[allow(unused)]
+#[path = "../src/manifest.rs"]
+mod manifest;
+#[allow(unused)]
+#[path = "../src/serial_recorder.rs"]
+mod serial_recorder;
+
+use std::{
+    env, fs,
+    path::PathBuf,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use manifest::RecordOptions;
+use serial_recorder::SerialRecorder;
+
+/// An empty directory to record into
+fn record_dir() -> PathBuf {
+    static NEXT_DIR: AtomicUsize = AtomicUsize::new(0);
+    let dir = env::temp_dir().join(format!(
+        "commands-mcp-recorder-{}-{}",
+        std::process::id(),
+        NEXT_DIR.fetch_add(1, Ordering::Relaxed)
+    ));
+    let _ = fs::remove_dir_all(&dir);
+    dir
+}
+
+/// Sizes of the files recorded, in the order they were created
+fn recorded(recorder: SerialRecorder) -> Vec<u64> {
+    let files = recorder.files();
+    drop(recorder);
+
+    let files = files.lock().unwrap().clone();
+    files
+        .iter()
+        .map(|file| fs::metadata(file).unwrap().len())
+        .collect()
+}
+
+#[test]
+fn rotates_by_size_before_writing() {
+    let dir = record_dir();
+    let opts = RecordOptions {
+        max_size: Some(8),
+        ..Default::default()
+    };
+    let mut recorder = SerialRecorder::create(&dir, "/dev/ttyUSB0", &opts).unwrap();
+
+    recorder.raw(b"0123");
+    recorder.raw(b"4567");
+    recorder.raw(b"89");
+
+    // The third write rotates, as the first files reached the maximum size
+    assert_eq!(recorded(recorder), [0, 8, 0, 2]);
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn does_not_rotate_after_the_last_write() {
+    let dir = record_dir();
+    let opts = RecordOptions {
+        max_size: Some(8),
+        ..Default::default()
+    };
+    let mut recorder = SerialRecorder::create(&dir, "/dev/ttyUSB0", &opts).unwrap();
+
+    // The last write reaches the maximum size, but flushing, including when
+    // dropping the recorder, doesn't open new files
+    recorder.raw(b"01234567");
+    recorder.flush();
+
+    assert_eq!(recorded(recorder), [0, 8]);
+    assert_eq!(fs::read_dir(&dir).unwrap().count(), 2);
+    fs::remove_dir_all(&dir).unwrap();
+}
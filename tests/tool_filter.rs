@@ -0,0 +1,121 @@
+//! Tests of the selection of tools by the profile and the command line
+
+#[allow(unused)]
+#[path = "../src/manifest.rs"]
+mod manifest;
+#[allow(unused)]
+#[path = "../src/tool_filter.rs"]
+mod tool_filter;
+
+use manifest::Profile;
+use tool_filter::ToolFilter;
+
+const TOOLS: [&str; 6] = [
+    "flash_write",
+    "flash_list_ports",
+    "serial_read",
+    "serial_write",
+    "serial_monitor_start",
+    "zephyr_build",
+];
+
+fn patterns(patterns: &[&str]) -> Vec<String> {
+    patterns.iter().map(|pattern| pattern.to_string()).collect()
+}
+
+fn profile(tools: &[&str], exclude_tools: &[&str]) -> Option<Profile> {
+    Some(Profile {
+        description: None,
+        tools: patterns(tools),
+        exclude_tools: patterns(exclude_tools),
+    })
+}
+
+/// The tools of [`TOOLS`] allowed by the filter
+fn allowed(filter: &ToolFilter) -> Vec<&'static str> {
+    TOOLS
+        .into_iter()
+        .filter(|name| filter.allows(name))
+        .collect()
+}
+
+#[test]
+fn allows_everything_by_default() {
+    assert_eq!(allowed(&ToolFilter::default()), TOOLS);
+}
+
+#[test]
+fn matches_globs() {
+    let cases: [(&[&str], &[&str]); 5] = [
+        (
+            &["serial_*"],
+            &["serial_read", "serial_write", "serial_monitor_start"],
+        ),
+        (&["*_write"], &["flash_write", "serial_write"]),
+        (&["serial_*_start"], &["serial_monitor_start"]),
+        (&["serial_????"], &["serial_read"]),
+        (
+            &["zephyr_build", "flash_*s"],
+            &["flash_list_ports", "zephyr_build"],
+        ),
+    ];
+
+    for (tools, expected) in cases {
+        let filter = ToolFilter::new(None, patterns(tools), vec![]);
+        assert_eq!(allowed(&filter), expected, "{:?}", tools);
+    }
+}
+
+#[test]
+fn matches_whole_names() {
+    for pattern in [
+        "serial",
+        "serial_rea",
+        "erial_read",
+        "serial_read?",
+        "?serial_read",
+    ] {
+        let filter = ToolFilter::new(None, patterns(&[pattern]), vec![]);
+        assert!(!filter.allows("serial_read"), "{}", pattern);
+    }
+
+    for pattern in ["*", "**", "serial_read*", "*serial_read", "s*r*a*d"] {
+        let filter = ToolFilter::new(None, patterns(&[pattern]), vec![]);
+        assert!(filter.allows("serial_read"), "{}", pattern);
+    }
+}
+
+#[test]
+fn excludes_take_precedence_over_includes() {
+    let filter = ToolFilter::new(None, patterns(&["serial_*"]), patterns(&["*_monitor_*"]));
+    assert_eq!(allowed(&filter), ["serial_read", "serial_write"]);
+}
+
+#[test]
+fn composes_profile_and_command_line() {
+    // Tools must be included by both, and excluded by neither
+    let filter = ToolFilter::new(
+        profile(&["serial_*", "flash_*"], &["flash_write"]),
+        patterns(&["*_write", "*_ports", "serial_monitor_start"]),
+        patterns(&["serial_monitor_*"]),
+    );
+    assert_eq!(allowed(&filter), ["flash_list_ports", "serial_write"]);
+
+    // The command line can't bring back a tool excluded by the profile
+    let filter = ToolFilter::new(profile(&[], &["zephyr_*"]), patterns(&["zephyr_*"]), vec![]);
+    assert!(allowed(&filter).is_empty());
+}
+
+#[test]
+fn reports_unmatched_patterns() {
+    let filter = ToolFilter::new(
+        profile(&["serial_*", "seral_*"], &["flash_erase"]),
+        patterns(&["*"]),
+        patterns(&["zephyr_?uild", "west_*"]),
+    );
+
+    assert_eq!(
+        filter.unmatched(&TOOLS),
+        ["seral_*", "flash_erase", "west_*"]
+    );
+}